@@ -4,6 +4,7 @@ use pyramid::pon::*;
 use std::cmp;
 use std::borrow::Cow;
 
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub struct Animatable {
     pub value: Vec<f32>
@@ -32,19 +33,56 @@ impl Animatable {
     }
 }
 
+impl From<f32> for Animatable {
+    fn from(value: f32) -> Animatable {
+        Animatable::new_float(value)
+    }
+}
+impl From<Vec<f32>> for Animatable {
+    fn from(value: Vec<f32>) -> Animatable {
+        Animatable::new(value)
+    }
+}
+impl From<[f32; 2]> for Animatable {
+    fn from(value: [f32; 2]) -> Animatable {
+        Animatable::new(value.to_vec())
+    }
+}
+impl From<[f32; 3]> for Animatable {
+    fn from(value: [f32; 3]) -> Animatable {
+        Animatable::new(value.to_vec())
+    }
+}
+impl From<[f32; 4]> for Animatable {
+    fn from(value: [f32; 4]) -> Animatable {
+        Animatable::new(value.to_vec())
+    }
+}
+
 impl ToPon for Animatable {
     fn to_pon(&self) -> Pon {
         match self.value.len() {
             1 => self.value[0].to_pon(),
             3 => Vector3::new(self.value[0], self.value[1], self.value[2]).to_pon(),
             4 => Vector4::new(self.value[3], self.value[0], self.value[1], self.value[2]).to_pon(),
-            _ => unreachable!()
+            _ => Pon::FloatArray(self.value.clone())
         }
     }
 }
 
 pub trait Interpolateable {
     fn interpolate(a: &Self, b: &Self, p: &f32) -> Self;
+    /// False if any component is NaN or infinite
+    fn is_finite(&self) -> bool {
+        true
+    }
+}
+
+/// Interpolates a and b, falling back to a if that gives NaN or infinite values,
+/// so bad keys don't spread NaNs into the properties they animate
+pub fn interpolate_finite<T: Interpolateable + Clone>(a: &T, b: &T, p: f32) -> T {
+    let res = Interpolateable::interpolate(a, b, &p);
+    if res.is_finite() { res } else { a.clone() }
 }
 
 impl Interpolateable for Animatable {
@@ -55,35 +93,114 @@ impl Interpolateable for Animatable {
         }
         Animatable { value: res }
     }
+    fn is_finite(&self) -> bool {
+        self.value.iter().all(|v| v.is_finite())
+    }
 }
 impl Interpolateable for f32 {
     fn interpolate(a: &f32, b: &f32, p: &f32) -> f32 {
         a * (1.0 - p) + b * p
     }
+    fn is_finite(&self) -> bool {
+        (*self).is_finite()
+    }
 }
 impl Interpolateable for Vector3<f32> {
     fn interpolate(a: &Vector3<f32>, b: &Vector3<f32>, p: &f32) -> Vector3<f32> {
         a.mul_s(1.0 - p) + b.mul_s(*p)
     }
+    fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite()
+    }
 }
 impl Interpolateable for Vector2<f32> {
     fn interpolate(a: &Vector2<f32>, b: &Vector2<f32>, p: &f32) -> Vector2<f32> {
         a.mul_s(1.0 - p) + b.mul_s(*p)
     }
+    fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite()
+    }
+}
+
+/// Parses '#rrggbb' or '#rrggbbaa' into components in the 0 to 1 range
+fn parse_hex_color(color: &str) -> Option<Animatable> {
+    if !color.starts_with("#") || !(color.len() == 7 || color.len() == 9) || !color.chars().skip(1).all(|c| c.is_digit(16)) {
+        return None;
+    }
+    let mut res = vec![];
+    for i in 0..(color.len() - 1) / 2 {
+        let start = 1 + i * 2;
+        res.push(u8::from_str_radix(&color[start..start + 2], 16).unwrap() as f32 / 255.0);
+    }
+    Some(Animatable::new(res))
 }
 
 impl Translatable<Animatable> for Pon {
     fn inner_translate(&self, context: &mut TranslateContext) -> Result<Animatable, PonTranslateErr> {
         if let Ok(v) = self.translate::<f32>(context) {
-            Ok(Animatable { value: vec![v] })
+            Ok(Animatable::from(v))
         } else if let Ok(v) = self.translate::<Vec<f32>>(context) {
-            Ok(Animatable { value: v })
+            Ok(Animatable::from(v))
         } else if let Ok(v) = self.translate::<Vector3<f32>>(context) {
             Ok(Animatable { value: vec![v.x, v.y, v.z] })
         } else if let Ok(v) = self.translate::<Vector4<f32>>(context) {
             Ok(Animatable { value: vec![v.x, v.y, v.z, v.w] })
+        } else if let Ok(color) = self.translate::<String>(context) {
+            match parse_hex_color(&color) {
+                Some(v) => Ok(v),
+                None => Err(PonTranslateErr::InvalidValue { value: color })
+            }
         } else {
             Err(PonTranslateErr::InvalidValue { value: self.to_string() })
         }
     }
 }
+
+#[test]
+fn test_hex_color() {
+    let color: Animatable = Pon::from_string("'#ff0000'").unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(color, Animatable::new(vec![1.0, 0.0, 0.0]));
+    let color: Animatable = Pon::from_string("'#ff880000'").unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(color.value.len(), 4);
+    assert!((color.value[1] - 0.5333).abs() < 0.001);
+    assert_eq!(color.value[3], 0.0);
+}
+
+#[test]
+fn test_invalid_hex_color() {
+    for color in &["'#ff00'", "'ff0000'", "'#gg0000'"] {
+        let res: Result<Animatable, PonTranslateErr> = Pon::from_string(color).unwrap().translate(&mut TranslateContext::empty());
+        assert!(res.is_err());
+    }
+}
+
+#[test]
+fn test_interpolate_f32() {
+    assert_eq!(Interpolateable::interpolate(&2.0f32, &4.0, &0.0), 2.0);
+    assert_eq!(Interpolateable::interpolate(&2.0f32, &4.0, &0.25), 2.5);
+    assert_eq!(Interpolateable::interpolate(&2.0f32, &4.0, &1.0), 4.0);
+}
+
+#[test]
+fn test_interpolate_animatable() {
+    let a = Animatable::new(vec![0.0, 2.0, -1.0]);
+    let b = Animatable::new(vec![1.0, 4.0, 1.0]);
+    assert_eq!(Interpolateable::interpolate(&a, &b, &0.5), Animatable::new(vec![0.5, 3.0, 0.0]));
+    assert_eq!(Interpolateable::interpolate(&a, &b, &1.0), b);
+}
+
+#[test]
+fn test_from() {
+    assert_eq!(Animatable::from(0.5), Animatable::new_float(0.5));
+    assert_eq!(Animatable::from([1.0, 2.0, 3.0]).value, vec![1.0, 2.0, 3.0]);
+    assert_eq!(Animatable::from([1.0, 2.0]).value.len(), 2);
+    assert_eq!(Animatable::from([1.0, 2.0, 3.0, 4.0]), Animatable::from(vec![1.0, 2.0, 3.0, 4.0]));
+}
+
+#[test]
+fn test_interpolate_finite() {
+    assert_eq!(interpolate_finite(&2.0f32, &4.0, 0.5), 3.0);
+    assert_eq!(interpolate_finite(&2.0f32, &4.0, ::std::f32::INFINITY), 2.0);
+    let a = Animatable::new(vec![1.0, 2.0]);
+    assert_eq!(interpolate_finite(&a, &Animatable::new(vec![3.0, ::std::f32::NAN]), 0.5), a);
+}
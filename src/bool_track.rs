@@ -0,0 +1,77 @@
+
+use time::*;
+use track::*;
+use pyramid::pon::*;
+use animatable::*;
+use curve::*;
+
+/// Switches a flag, like visibility, on and off. Each key holds its value until the next key,
+/// the value is 1.0 for true and 0.0 for false. Before the first key the first key's value is used,
+/// and the last value is kept after the last key.
+#[derive(Debug)]
+pub struct BoolTrack {
+    pub property: NamedPropRef,
+    pub keys: LinearKeyFrameCurve<bool>
+}
+
+impl BoolTrack {
+    pub fn flag_at(&self, time: Duration) -> bool {
+        *self.keys.segment(time.num_milliseconds() as f32 / 1000.0).0
+    }
+}
+
+impl Track for BoolTrack {
+    fn value_at(&self, time: Duration) -> Vec<(NamedPropRef, Animatable)> {
+        let value = if self.flag_at(time) { 1.0 } else { 0.0 };
+        vec![(self.property.clone(), Animatable::new_float(value))]
+    }
+    fn affected_properties(&self) -> Vec<NamedPropRef> {
+        vec![self.property.clone()]
+    }
+    fn duration(&self) -> Duration {
+        let last = self.keys.keys().last().map(|key| key.0).unwrap_or(0.0);
+        Duration::milliseconds((last.max(0.0) * 1000.0) as i64)
+    }
+    fn is_finished(&self, _time: Duration) -> bool {
+        // Keeps producing the last value
+        false
+    }
+}
+
+impl Translatable<Key<bool>> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<Key<bool>, PonTranslateErr> {
+        translate_key(self, context)
+    }
+}
+
+impl Translatable<BoolTrack> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<BoolTrack, PonTranslateErr> {
+        self.as_typed(|&TypedPon { ref type_name, ref data }| {
+            match type_name.as_str() {
+                "bool_track" => {
+                    let property: &NamedPropRef = try!(try!(data.field("property")).as_reference());
+                    let keys = try!(translate_keys::<Key<bool>>(try!(data.field("keys")), context));
+                    Ok(BoolTrack {
+                        property: property.clone(),
+                        keys: LinearKeyFrameCurve::new(try!(sort_keys(keys)))
+                    })
+                },
+                s @ _ => Err(PonTranslateErr::UnrecognizedType(s.to_string()))
+            }
+        })
+    }
+}
+
+#[test]
+fn test_bool_track() {
+    let track: BoolTrack = Pon::from_string(
+        "bool_track { property: this.visible, keys: [{ time: 0.0, value: false }, { time: 1.0, value: true }] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let visible = |value| vec![(NamedPropRef::new(EntityPath::This, "visible"), Animatable::new_float(value))];
+    assert_eq!(track.value_at(Duration::zero()), visible(0.0));
+    assert_eq!(track.value_at(Duration::milliseconds(500)), visible(0.0));
+    assert_eq!(track.value_at(Duration::milliseconds(999)), visible(0.0));
+    assert_eq!(track.value_at(Duration::seconds(1)), visible(1.0));
+    assert_eq!(track.value_at(Duration::seconds(5)), visible(1.0));
+    assert_eq!(track.duration(), Duration::seconds(1));
+}
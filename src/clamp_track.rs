@@ -0,0 +1,91 @@
+
+use time::*;
+use track::*;
+use pyramid::pon::*;
+use animatable::*;
+use event_track::*;
+
+/// Keeps every value a track produces within per component bounds.
+/// Components without a bound are left as they are.
+#[derive(Debug)]
+pub struct ClampTrack {
+    pub track: Box<Track>,
+    pub min: Animatable,
+    pub max: Animatable
+}
+
+impl ClampTrack {
+    fn clamp(&self, values: &mut [(NamedPropRef, Animatable)]) {
+        for update in values.iter_mut() {
+            let value = update.1.value.iter().enumerate().map(|(i, &x)| {
+                let x = match self.min.value.get(i) { Some(&min) => x.max(min), None => x };
+                match self.max.value.get(i) { Some(&max) => x.min(max), None => x }
+            }).collect();
+            update.1 = Animatable::new(value);
+        }
+    }
+}
+
+impl Track for ClampTrack {
+    fn value_at(&self, time: Duration) -> Vec<(NamedPropRef, Animatable)> {
+        let mut values = self.track.value_at(time);
+        self.clamp(&mut values);
+        values
+    }
+    fn value_at_reusing(&self, time: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) {
+        self.track.value_at_reusing(time, out);
+        self.clamp(out);
+    }
+    fn seek_reusing(&self, from: Duration, to: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) -> Vec<Event> {
+        let events = self.track.seek_reusing(from, to, out);
+        self.clamp(out);
+        events
+    }
+    fn affected_properties(&self) -> Vec<NamedPropRef> {
+        self.track.affected_properties()
+    }
+    fn duration(&self) -> Duration {
+        self.track.duration()
+    }
+    fn is_finished(&self, time: Duration) -> bool {
+        self.track.is_finished(time)
+    }
+    fn events_between(&self, from: Duration, to: Duration) -> Vec<Event> {
+        self.track.events_between(from, to)
+    }
+}
+
+impl Translatable<ClampTrack> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<ClampTrack, PonTranslateErr> {
+        self.as_typed(|&TypedPon { ref type_name, ref data }| {
+            match type_name.as_str() {
+                "clamp_track" => {
+                    Ok(ClampTrack {
+                        track: try!(data.field_as::<Box<Track>>("track", context)),
+                        min: try!(data.field_as_or("min", Animatable::new(vec![]), context)),
+                        max: try!(data.field_as_or("max", Animatable::new(vec![]), context))
+                    })
+                },
+                s @ _ => Err(PonTranslateErr::UnrecognizedType(s.to_string()))
+            }
+        })
+    }
+}
+
+#[test]
+fn test_clamp_overshoot() {
+    let clamped: ClampTrack = Pon::from_string(
+        "clamp_track { track: key_framed { property: this.opacity, keys: [[0.0, 0.0], [0.5, 1.5], [1.0, -0.5]] }, min: [0.0], max: [1.0] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(clamped.value_at(Duration::milliseconds(250)), vec![(NamedPropRef::new(EntityPath::This, "opacity"), Animatable::new_float(0.75))]);
+    assert_eq!(clamped.value_at(Duration::milliseconds(500)), vec![(NamedPropRef::new(EntityPath::This, "opacity"), Animatable::new_float(1.0))]);
+    assert_eq!(clamped.value_at(Duration::seconds(1)), vec![(NamedPropRef::new(EntityPath::This, "opacity"), Animatable::new_float(0.0))]);
+}
+
+#[test]
+fn test_clamp_unbounded_components() {
+    let clamped: ClampTrack = Pon::from_string(
+        "clamp_track { track: fixed_value { property: this.scale, value: [2.0, 3.0] }, max: [1.0] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(clamped.value_at(Duration::zero()), vec![(NamedPropRef::new(EntityPath::This, "scale"), Animatable::new(vec![1.0, 3.0]))]);
+}
@@ -0,0 +1,153 @@
+
+use pyramid::pon::*;
+#[cfg(test)]
+use time::*;
+#[cfg(test)]
+use track::*;
+#[cfg(test)]
+use curve_track::*;
+#[cfg(test)]
+use animatable::*;
+
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Debug, Clone)]
+pub enum Loop {
+    Forever,
+    Once,
+    /// Plays forwards, then backwards, then forwards again and so on
+    PingPong,
+    /// Plays the given number of times, then stops
+    Count(u32),
+    /// Plays once, then keeps the end value
+    Hold,
+    /// Plays once like Once, but a seek that crosses the end still gets the end value,
+    /// so the last frame shows where the track ended rather than where it was a frame earlier.
+    /// The animation subsystem seeks from the previous update, and the built in tracks that wrap
+    /// other tracks seek them in turn.
+    OnceThenHoldOneFrame
+}
+
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Debug, Clone)]
+pub enum CurveTime {
+    /// The curve is expected to have keys between 0 and 1
+    Relative,
+    /// The curve is expected to have keys between 0 and duration
+    Absolute,
+    /// The curve is expected to have keys between 0 and 100, in percent of duration
+    Percent,
+    /// The curve's keys are in frames, at the given frames per second
+    Frames(f32),
+    /// The curve's keys are in beats, at the given beats per minute
+    Beats(f32)
+}
+
+fn translate_loop_count(pon: &Pon, context: &mut TranslateContext) -> Result<Loop, PonTranslateErr> {
+    let count = try!(pon.translate::<f32>(context));
+    if count < 1.0 || count.fract() != 0.0 {
+        return Err(PonTranslateErr::InvalidValue { value: format!("{:?}", pon) });
+    }
+    Ok(Loop::Count(count as u32))
+}
+
+impl ToPon for Loop {
+    fn to_pon(&self) -> Pon {
+        match *self {
+            Loop::Forever => Pon::String("forever".to_string()),
+            Loop::Once => Pon::String("once".to_string()),
+            Loop::PingPong => Pon::String("ping_pong".to_string()),
+            Loop::Hold => Pon::String("hold".to_string()),
+            Loop::OnceThenHoldOneFrame => Pon::String("once_then_hold_one_frame".to_string()),
+            Loop::Count(n) => Pon::Array(vec![Pon::String("count".to_string()), Pon::Number(n as f32)])
+        }
+    }
+}
+
+impl Translatable<Loop> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<Loop, PonTranslateErr> {
+        match self {
+            &Pon::Array(ref arr) if arr.len() == 2 => {
+                match try!(arr[0].translate::<String>(context)).as_str() {
+                    "count" => translate_loop_count(&arr[1], context),
+                    _ => Err(PonTranslateErr::InvalidValue { value: format!("{:?}", self) })
+                }
+            },
+            _ => {
+                match self.translate::<String>(context) {
+                    Ok(name) => match name.as_str() {
+                        "forever" => Ok(Loop::Forever),
+                        "once" => Ok(Loop::Once),
+                        "ping_pong" => Ok(Loop::PingPong),
+                        "hold" => Ok(Loop::Hold),
+                        "once_then_hold_one_frame" => Ok(Loop::OnceThenHoldOneFrame),
+                        _ => Err(PonTranslateErr::InvalidValue { value: format!("{:?}", self) })
+                    },
+                    Err(_) => translate_loop_count(self, context)
+                }
+            }
+        }
+    }
+}
+
+impl ToPon for CurveTime {
+    fn to_pon(&self) -> Pon {
+        match *self {
+            CurveTime::Absolute => Pon::String("absolute".to_string()),
+            CurveTime::Relative => Pon::String("relative".to_string()),
+            CurveTime::Percent => Pon::String("percent".to_string()),
+            CurveTime::Frames(fps) => Pon::Array(vec![Pon::String("frames".to_string()), Pon::Number(fps)]),
+            CurveTime::Beats(bpm) => Pon::Array(vec![Pon::String("beats".to_string()), Pon::Number(bpm)])
+        }
+    }
+}
+
+impl Translatable<CurveTime> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<CurveTime, PonTranslateErr> {
+        match self {
+            &Pon::Array(ref arr) if arr.len() == 2 => {
+                let rate = try!(arr[1].translate::<f32>(context));
+                if rate <= 0.0 {
+                    return Err(PonTranslateErr::InvalidValue { value: format!("{:?}", self) });
+                }
+                match try!(arr[0].translate::<String>(context)).as_str() {
+                    "frames" => Ok(CurveTime::Frames(rate)),
+                    "beats" => Ok(CurveTime::Beats(rate)),
+                    _ => Err(PonTranslateErr::InvalidValue { value: format!("{:?}", self) })
+                }
+            },
+            _ => {
+                match try!(self.translate::<String>(context)).as_str() {
+                    "absolute" => Ok(CurveTime::Absolute),
+                    "relative" => Ok(CurveTime::Relative),
+                    "percent" => Ok(CurveTime::Percent),
+                    _ => Err(PonTranslateErr::InvalidValue { value: format!("{:?}", self) })
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_shared_enums_in_curve_track() {
+    let mut track: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], duration: 1.0, loop: 'ping_pong', curve_time: 'relative' }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(track.loop_type, Loop::PingPong);
+    assert_eq!(track.curve_time, CurveTime::Relative);
+    assert_eq!(track.value_at(Duration::milliseconds(1500)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.5))]);
+    track.loop_type = Loop::Once;
+    assert_eq!(track.value_at(Duration::milliseconds(1500)), vec![]);
+}
+
+#[test]
+fn test_shared_enums_to_pon() {
+    for source in &["'forever'", "'hold'", "['count', 2]"] {
+        let pon = Pon::from_string(source).unwrap();
+        let loop_type: Loop = pon.translate(&mut TranslateContext::empty()).unwrap();
+        assert_eq!(loop_type.to_pon(), pon);
+    }
+    let pon = Pon::from_string("['beats', 120]").unwrap();
+    let curve_time: CurveTime = pon.translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(curve_time, CurveTime::Beats(120.0));
+    assert_eq!(curve_time.to_pon(), pon);
+}
@@ -0,0 +1,125 @@
+#[cfg(test)]
+use curve_track::*;
+
+use std::cmp;
+use std::collections::HashMap;
+use time::*;
+use track::*;
+use pyramid::pon::*;
+use animatable::*;
+use event_track::*;
+
+/// Fades from track a (weight 0) to track b (weight 1).
+#[derive(Debug)]
+pub struct CrossfadeTrack {
+    pub a: Box<Track>,
+    pub b: Box<Track>,
+    pub weight: f32
+}
+
+impl CrossfadeTrack {
+    fn mix(&self, a_values: Vec<(NamedPropRef, Animatable)>, b_values: Vec<(NamedPropRef, Animatable)>) -> Vec<(NamedPropRef, Animatable)> {
+        let mut b_values: HashMap<NamedPropRef, Animatable> = b_values.into_iter().collect();
+        let mut res = vec![];
+        for (prop, a_value) in a_values {
+            let value = match b_values.remove(&prop) {
+                Some(b_value) => Interpolateable::interpolate(&a_value, &b_value, &self.weight),
+                None => a_value.weighted(1.0 - self.weight)
+            };
+            res.push((prop, value));
+        }
+        for (prop, b_value) in b_values {
+            res.push((prop, b_value.weighted(self.weight)));
+        }
+        res
+    }
+}
+
+impl Track for CrossfadeTrack {
+    fn value_at(&self, time: Duration) -> Vec<(NamedPropRef, Animatable)> {
+        self.mix(self.a.value_at(time), self.b.value_at(time))
+    }
+    fn seek_reusing(&self, from: Duration, to: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) -> Vec<Event> {
+        let (a, b) = (self.a.seek(from, to), self.b.seek(from, to));
+        let mut events = a.events;
+        events.extend(b.events.into_iter());
+        *out = self.mix(a.values, b.values);
+        events
+    }
+    fn affected_properties(&self) -> Vec<NamedPropRef> {
+        let mut res = self.a.affected_properties();
+        add_properties(&mut res, self.b.affected_properties());
+        res
+    }
+    fn duration(&self) -> Duration {
+        cmp::max(self.a.duration(), self.b.duration())
+    }
+    fn is_finished(&self, time: Duration) -> bool {
+        self.a.is_finished(time) && self.b.is_finished(time)
+    }
+    fn events_between(&self, from: Duration, to: Duration) -> Vec<Event> {
+        let mut res = self.a.events_between(from, to);
+        res.extend(self.b.events_between(from, to).into_iter());
+        res
+    }
+}
+
+impl Translatable<CrossfadeTrack> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<CrossfadeTrack, PonTranslateErr> {
+        self.as_typed(|&TypedPon { ref type_name, ref data }| {
+            match type_name.as_str() {
+                "crossfade" => {
+                    Ok(CrossfadeTrack {
+                        a: try!(data.field_as::<Box<Track>>("a", context)),
+                        b: try!(data.field_as::<Box<Track>>("b", context)),
+                        weight: try!(data.field_as_or("weight", 0.5, context))
+                    })
+                },
+                s @ _ => Err(PonTranslateErr::UnrecognizedType(s.to_string()))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+fn fixed(prop: &str, value: f32) -> Box<Track> {
+    Box::new(CurveTrack::new_fixed_value(NamedPropRef::new(EntityPath::This, prop), Animatable::new_float(value)))
+}
+
+#[test]
+fn test_crossfade_ends() {
+    let mut fade = CrossfadeTrack { a: fixed("x", 2.0), b: fixed("x", 4.0), weight: 0.0 };
+    assert_eq!(fade.value_at(Duration::zero()), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(2.0))]);
+    fade.weight = 1.0;
+    assert_eq!(fade.value_at(Duration::zero()), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(4.0))]);
+}
+
+#[test]
+fn test_crossfade_midway() {
+    let fade: Box<Track> = Pon::from_string(
+        "crossfade { a: fixed_value { property: this.x, value: 2.0 }, b: fixed_value { property: this.y, value: 4.0 }, weight: 0.5 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(fade.value_at(Duration::zero()), vec![
+        (NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(1.0)),
+        (NamedPropRef::new(EntityPath::This, "y"), Animatable::new_float(2.0))
+    ]);
+    let fade = CrossfadeTrack { a: fixed("x", 2.0), b: fixed("x", 4.0), weight: 0.5 };
+    assert_eq!(fade.value_at(Duration::zero()), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(3.0))]);
+}
+
+#[test]
+fn test_crossfade_duration() {
+    let fade: CrossfadeTrack = Pon::from_string(
+        "crossfade {
+            a: key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], duration: 1.0 },
+            b: key_framed { property: this.x, keys: [[0.0, 0.0], [2.0, 1.0]], duration: 2.5 }
+        }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(fade.duration(), Duration::milliseconds(2500));
+}
+
+#[test]
+fn test_crossfade_affected_properties() {
+    let fade = CrossfadeTrack { a: fixed("x", 2.0), b: fixed("y", 4.0), weight: 0.5 };
+    assert_eq!(fade.affected_properties(), this_props(&["x", "y"]));
+}
@@ -1,10 +1,139 @@
-#[cfg(test)]
 use cgmath::*;
+use std::fmt;
 use std::fmt::Debug;
+use std::cmp::Ordering;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use pyramid::pon::*;
 use animatable::*;
+use curve_ops::*;
+use easing::*;
+use spline::*;
+use interpolation::*;
+use track::{ThreadSafety, Shared};
 
-pub trait Curve<T> : Debug {
+pub trait Curve<T> : Debug + ThreadSafety {
     fn value(&self, time: f32) -> T;
+    /// The first and last time the curve is defined for
+    fn domain(&self) -> (f32, f32);
+    /// Value at time, with time clamped into the domain of the curve
+    fn value_clamped(&self, time: f32) -> T {
+        let (start, end) = self.domain();
+        self.value(time.max(start).min(end))
+    }
+    /// Value of the last key at or before time, without blending towards the next key.
+    /// Curves that aren't made of keys just return their value.
+    fn stepped_value(&self, time: f32) -> T {
+        self.value(time)
+    }
+    /// The curve in the form it's translated from, for the curves that have one
+    fn to_pon(&self) -> Option<Pon> where T: ToPon {
+        None
+    }
+    /// The curve as CurveData, for the built in key frame curves
+    fn to_data(&self) -> Option<CurveData<T>> where T: Clone {
+        None
+    }
+    /// True if the curve has the same value at every time
+    fn is_constant(&self) -> bool {
+        false
+    }
+    /// Times of the curve's keys. Linearly interpolated curves only have extremes at their keys,
+    /// so sampling these finds their exact range.
+    fn key_times(&self) -> Vec<f32> {
+        vec![]
+    }
+}
+
+impl<T> Curve<T> {
+    /// Lazily samples count evenly spaced times from from to to, ends included, as (time, value)
+    pub fn samples(&self, from: f32, to: f32, count: usize) -> Samples<T> {
+        Samples {
+            curve: self,
+            from: from,
+            to: to,
+            count: count,
+            next: 0
+        }
+    }
+}
+
+/// Iterator returned by Curve::samples
+pub struct Samples<'a, T: 'a> {
+    curve: &'a Curve<T>,
+    from: f32,
+    to: f32,
+    count: usize,
+    next: usize
+}
+
+impl<'a, T> Iterator for Samples<'a, T> {
+    type Item = (f32, T);
+    fn next(&mut self) -> Option<(f32, T)> {
+        if self.next >= self.count {
+            return None;
+        }
+        let time = if self.count == 1 {
+            self.from
+        } else {
+            self.from + (self.to - self.from) * self.next as f32 / (self.count - 1) as f32
+        };
+        self.next += 1;
+        Some((time, self.curve.value(time)))
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.count - self.next;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Samples<'a, T> {}
+
+/// Translates an array of keys, reporting which key failed if any does.
+/// Curves need at least one key, so an empty array is an error.
+pub fn translate_keys<T>(keys: &Pon, context: &mut TranslateContext) -> Result<Vec<T>, PonTranslateErr> where Pon: Translatable<T> {
+    let res = try!(translate_key_list(keys, context));
+    if res.is_empty() {
+        return Err(PonTranslateErr::InvalidValue { value: "keys can't be empty".to_string() });
+    }
+    Ok(res)
+}
+
+fn translate_key_list<T>(keys: &Pon, context: &mut TranslateContext) -> Result<Vec<T>, PonTranslateErr> where Pon: Translatable<T> {
+    match keys {
+        &Pon::Array(ref arr) => {
+            let mut res = vec![];
+            for (i, key) in arr.iter().enumerate() {
+                match key.translate::<T>(context) {
+                    Ok(key) => res.push(key),
+                    Err(err) => return Err(PonTranslateErr::InvalidValue { value: format!("keys[{}]: {}", i, err.to_string()) })
+                }
+            }
+            Ok(res)
+        },
+        _ => Ok(try!(keys.translate::<PonAutoVec<T>>(context)).0)
+    }
+}
+
+/// Sorts keys by time, since curves expect them in order. Two keys at the same time
+/// leave it ambiguous which value applies, so that's an error.
+pub fn sort_keys<T: Clone>(mut keys: Vec<Key<T>>) -> Result<Vec<Key<T>>, PonTranslateErr> {
+    keys.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+    for i in 1..keys.len() {
+        if keys[i].0 == keys[i - 1].0 {
+            return Err(PonTranslateErr::InvalidValue { value: format!("more than one key at time {}", keys[i].0) });
+        }
+    }
+    Ok(keys)
+}
+
+/// Builds the Pon for `type_name { data }`
+pub fn typed_pon(type_name: &str, data: Pon) -> Pon {
+    Pon::TypedPon(Box::new(TypedPon { type_name: type_name.to_string(), data: data }))
+}
+
+/// Builds a Pon object from its fields
+pub fn object_pon(fields: Vec<(&str, Pon)>) -> Pon {
+    Pon::Object(fields.into_iter().map(|(name, value)| (name.to_string(), value)).collect())
 }
 
 
@@ -12,18 +141,201 @@ pub trait Curve<T> : Debug {
 pub struct FixedValueCurve<T> {
     pub value: T
 }
-impl<T: Debug + Clone> Curve<T> for FixedValueCurve<T> {
+impl<T: Debug + Clone + ThreadSafety> Curve<T> for FixedValueCurve<T> {
     fn value(&self, _: f32) -> T {
         self.value.clone()
     }
+    fn to_pon(&self) -> Option<Pon> where T: ToPon {
+        Some(typed_pon("fixed_value", self.value.to_pon()))
+    }
+    fn to_data(&self) -> Option<CurveData<T>> where T: Clone {
+        Some(CurveData::FixedValue(self.value.clone()))
+    }
+    fn is_constant(&self) -> bool {
+        true
+    }
+    fn domain(&self) -> (f32, f32) {
+        (0.0, 0.0)
+    }
 }
 
-#[derive(PartialEq, Debug)]
+/// A curve shared between several tracks, such as one registered as a document resource
+#[derive(Debug)]
+pub struct SharedCurve<T> {
+    pub curve: Shared<Box<Curve<T>>>
+}
+
+impl<T: Debug> Curve<T> for SharedCurve<T> {
+    fn value(&self, time: f32) -> T {
+        self.curve.value(time)
+    }
+    fn domain(&self) -> (f32, f32) {
+        self.curve.domain()
+    }
+    fn stepped_value(&self, time: f32) -> T {
+        self.curve.stepped_value(time)
+    }
+    fn is_constant(&self) -> bool {
+        self.curve.is_constant()
+    }
+    fn key_times(&self) -> Vec<f32> {
+        self.curve.key_times()
+    }
+    // Written out as the shared curve itself, since the resource id isn't kept
+    fn to_pon(&self) -> Option<Pon> where T: ToPon {
+        self.curve.to_pon()
+    }
+    fn to_data(&self) -> Option<CurveData<T>> where T: Clone {
+        self.curve.to_data()
+    }
+}
+
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Debug, Clone)]
 pub struct Key<T: Clone>(pub f32, pub T);
 
+/// Remembers the segment the last sample fell in, since curves are mostly sampled with increasing time.
+/// It's atomic so the curve can still be shared between threads, but isn't part of the curve's value.
+pub struct SegmentCache(AtomicUsize);
+
+impl SegmentCache {
+    pub fn new() -> SegmentCache {
+        SegmentCache(AtomicUsize::new(0))
+    }
+}
+
+impl PartialEq for SegmentCache {
+    fn eq(&self, _: &SegmentCache) -> bool {
+        true
+    }
+}
+
+impl Debug for SegmentCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SegmentCache({})", self.0.load(AtomicOrdering::Relaxed))
+    }
+}
+
+/// The data of the built in key frame curves, for storing curves outside of Pon
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Debug, Clone)]
+pub enum CurveData<T: Clone> {
+    FixedValue(T),
+    KeyFramed {
+        keys: Vec<Key<T>>,
+        easing: Easing,
+        interpolation: Interpolation,
+        key_interpolations: Vec<Option<Interpolation>>,
+        /// Set for cyclic curves, see LinearKeyFrameCurve::set_cyclic
+        cycle_period: Option<f32>
+    }
+}
+
+impl CurveData<Animatable> {
+    pub fn into_curve(self) -> Box<Curve<Animatable>> {
+        match self {
+            CurveData::FixedValue(value) => Box::new(FixedValueCurve { value: value }),
+            CurveData::KeyFramed { keys, easing, interpolation, key_interpolations, cycle_period } => {
+                let mut linear = LinearKeyFrameCurve::new(keys);
+                linear.set_cyclic(cycle_period);
+                InterpolatedKeyFrameCurve::new_boxed(linear, interpolation, key_interpolations, easing)
+            }
+        }
+    }
+}
+
+/// Linear interpolation between keys sorted by time.
+///
+/// The keys are private, since the per segment data below is computed from them and has to be kept
+/// in step with them. Build curves with `new`, read the keys with `keys()`, and change them with
+/// `set_keys`, `add_key`, `remove_key_at` or `clear_keys`.
 #[derive(PartialEq, Debug)]
 pub struct LinearKeyFrameCurve<T: Clone> {
-    pub keys: Vec<Key<T>>
+    keys: Vec<Key<T>>,
+    /// 1 / (t1 - t0) for each segment, so sampling doesn't need a division
+    inverse_durations: Vec<f32>,
+    last_segment: SegmentCache,
+    /// When set the curve repeats with this period, see set_cyclic
+    cycle_period: Option<f32>
+}
+
+impl<T: Clone> LinearKeyFrameCurve<T> {
+    pub fn new(keys: Vec<Key<T>>) -> LinearKeyFrameCurve<T> {
+        let mut curve = LinearKeyFrameCurve {
+            keys: keys,
+            inverse_durations: vec![],
+            last_segment: SegmentCache::new(),
+            cycle_period: None
+        };
+        curve.update_segments();
+        curve
+    }
+    /// The keys, sorted by time
+    pub fn keys(&self) -> &[Key<T>] {
+        &self.keys
+    }
+    /// Replaces all keys, which have to be sorted by time
+    pub fn set_keys(&mut self, keys: Vec<Key<T>>) {
+        self.keys = keys;
+        self.update_segments();
+    }
+    /// Inserts key in time order, replacing any key already at the same time
+    pub fn add_key(&mut self, key: Key<T>) {
+        let n = self.keys_at_or_before(key.0);
+        if n > 0 && self.keys[n - 1].0 == key.0 {
+            self.keys[n - 1] = key;
+        } else {
+            self.keys.insert(n, key);
+        }
+        self.update_segments();
+    }
+    /// Removes the key at exactly time, if there is one
+    pub fn remove_key_at(&mut self, time: f32) -> Option<Key<T>> {
+        match self.keys.iter().position(|key| key.0 == time) {
+            Some(i) => {
+                let key = self.keys.remove(i);
+                self.update_segments();
+                Some(key)
+            },
+            None => None
+        }
+    }
+    /// Makes the curve repeat every period, starting at the first key. After the last key it
+    /// interpolates towards the first key of the next period instead of holding the last key,
+    /// so loops whose first and last keys differ don't snap. The key_framed translation sets the
+    /// period from the track's duration, setting a different duration later needs a new period.
+    pub fn set_cyclic(&mut self, period: Option<f32>) {
+        self.cycle_period = period;
+    }
+    pub fn cycle_period(&self) -> Option<f32> {
+        self.cycle_period
+    }
+    /// True after clear_keys, or for a curve created without keys. Such a curve can't be
+    /// sampled until a key is added.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+    pub fn clear_keys(&mut self) {
+        self.keys.clear();
+        self.update_segments();
+    }
+    /// Recomputes inverse_durations, and forgets the cached segment, after the keys change
+    fn update_segments(&mut self) {
+        self.inverse_durations = self.keys.windows(2).map(|w| 1.0 / (w[1].0 - w[0].0)).collect();
+        self.last_segment = SegmentCache::new();
+    }
+    /// The keys as `[[time, value], ...]`
+    pub fn keys_to_pon(&self) -> Pon where T: ToPon {
+        Pon::Array(self.keys.iter().map(|key| Pon::Array(vec![Pon::Number(key.0), key.1.to_pon()])).collect())
+    }
+    /// A `key_framed` Pon with fields, marked cyclic if the curve is. The period isn't written,
+    /// translating derives it from the track's duration.
+    pub fn key_framed_pon(&self, mut fields: Vec<(&str, Pon)>) -> Pon {
+        if self.cycle_period.is_some() {
+            fields.push(("cyclic", Pon::Boolean(true)));
+        }
+        typed_pon("key_framed", object_pon(fields))
+    }
 }
 
 impl<T: Interpolateable + Debug + Clone> LinearKeyFrameCurve<T> {
@@ -37,34 +349,105 @@ impl<T: Interpolateable + Debug + Clone> LinearKeyFrameCurve<T> {
     }
 }
 
-impl<T: Interpolateable + Debug + Clone> Curve<T> for LinearKeyFrameCurve<T> {
-    fn value(&self, time: f32) -> T {
-        let mut key_before = None;
-        let mut key_after = None;
-        for i in 0..self.keys.len() {
-            if self.keys[i].0 > time { break; }
-            else { key_before = Some(&self.keys[i]); }
-        }
-        for i in 0..self.keys.len() {
-            if self.keys[i].0 > time {
-                key_after = Some(&self.keys[i]);
-                break;
+impl<T: Clone> LinearKeyFrameCurve<T> {
+    /// True if exactly the first n keys are at or before time
+    fn is_split_at(&self, n: usize, time: f32) -> bool {
+        n <= self.keys.len() &&
+            (n == 0 || self.keys[n - 1].0 <= time) &&
+            (n == self.keys.len() || self.keys[n].0 > time)
+    }
+    /// The number of keys at or before time. The segment from the last sample and the one after
+    /// it are tried first, falling back to a binary search since the keys are sorted.
+    pub fn keys_at_or_before(&self, time: f32) -> usize {
+        let last = self.last_segment.0.load(AtomicOrdering::Relaxed);
+        let n = if self.is_split_at(last, time) {
+            last
+        } else if self.is_split_at(last + 1, time) {
+            last + 1
+        } else {
+            // The comparison never returns Equal, so the search always ends with Err
+            match self.keys.binary_search_by(|key| if key.0 <= time { Ordering::Less } else { Ordering::Greater }) {
+                Ok(i) => i,
+                Err(i) => i
             }
         };
-        let key_before = match key_before {
-            Some(k) => k,
-            None => return Interpolateable::interpolate(&self.keys[0].1, &self.keys[0].1, &0.0)
-        };
-        let key_after = match key_after {
-            Some(k) => k,
-            None => {
-                let k = &self.keys[self.keys.len() - 1].1;
-                return Interpolateable::interpolate(k, k, &0.0)
+        self.last_segment.0.store(n, AtomicOrdering::Relaxed);
+        n
+    }
+    /// Finds the keys surrounding time, and how far between them time is (0 to 1).
+    /// Outside of the keys the closest key is returned as both ends, unless the curve is cyclic.
+    /// Panics if the curve has no keys, since there is no value to return; check is_empty first.
+    pub fn segment(&self, mut time: f32) -> (&T, &T, f32) {
+        assert!(!self.keys.is_empty(), "sampled a key frame curve without keys");
+        if let Some(period) = self.cycle_period {
+            // Times outside of the first period map to the same point in it
+            let first = self.keys[0].0;
+            if period > 0.0 && (time < first || time > first + period) {
+                let offset = (time - first) % period;
+                time = first + if offset < 0.0 { offset + period } else { offset };
             }
-        };
-        let d = key_after.0 - key_before.0;
-        let p = (time - key_before.0) / d;
-        return Interpolateable::interpolate(&key_before.1, &key_after.1, &p);
+        }
+        let after = self.keys_at_or_before(time);
+        if let Some(period) = self.cycle_period {
+            if after == 0 || after == self.keys.len() {
+                return self.wrap_segment(time, period);
+            }
+        }
+        if after == 0 {
+            return (&self.keys[0].1, &self.keys[0].1, 0.0);
+        }
+        if after == self.keys.len() {
+            let k = &self.keys[self.keys.len() - 1].1;
+            return (k, k, 0.0);
+        }
+        let key_before = &self.keys[after - 1];
+        let p = (time - key_before.0) * self.inverse_durations[after - 1];
+        if !p.is_finite() {
+            // Zero length segment, keep the value of the key before it
+            return (&key_before.1, &key_before.1, 0.0);
+        }
+        (&key_before.1, &self.keys[after].1, p)
+    }
+    /// The segment from the last key to the first key of the next cycle
+    fn wrap_segment(&self, time: f32, period: f32) -> (&T, &T, f32) {
+        let (first, last) = (&self.keys[0], &self.keys[self.keys.len() - 1]);
+        let start = if time < first.0 { last.0 - period } else { last.0 };
+        let p = (time - start) / (first.0 + period - last.0);
+        if !p.is_finite() {
+            return (&last.1, &last.1, 0.0);
+        }
+        (&last.1, &first.1, p.max(0.0).min(1.0))
+    }
+}
+
+impl<T: Interpolateable + Debug + Clone + ThreadSafety> Curve<T> for LinearKeyFrameCurve<T> {
+    fn value(&self, time: f32) -> T {
+        let (a, b, p) = self.segment(time);
+        interpolate_finite(a, b, p)
+    }
+    fn stepped_value(&self, time: f32) -> T {
+        self.segment(time).0.clone()
+    }
+    fn key_times(&self) -> Vec<f32> {
+        self.keys.iter().map(|key| key.0).collect()
+    }
+    fn to_pon(&self) -> Option<Pon> where T: ToPon {
+        Some(self.key_framed_pon(vec![("keys", self.keys_to_pon())]))
+    }
+    fn to_data(&self) -> Option<CurveData<T>> where T: Clone {
+        Some(CurveData::KeyFramed {
+            keys: self.keys.clone(),
+            easing: Easing::Linear,
+            interpolation: Interpolation::Linear,
+            key_interpolations: vec![],
+            cycle_period: self.cycle_period
+        })
+    }
+    fn domain(&self) -> (f32, f32) {
+        match (self.keys.first(), self.keys.last()) {
+            (Some(first), Some(last)) => (first.0, last.0),
+            _ => (0.0, 0.0)
+        }
     }
 }
 
@@ -73,7 +456,7 @@ pub struct DiscreetKeyFrameCurve<T: Clone> {
     pub keys: Vec<Key<T>>
 }
 
-impl<T: Interpolateable + Debug + Clone> Curve<T> for DiscreetKeyFrameCurve<T> {
+impl<T: Interpolateable + Debug + Clone + ThreadSafety> Curve<T> for DiscreetKeyFrameCurve<T> {
     fn value(&self, time: f32) -> T {
         let i = (time * self.keys.len() as f32) as usize;
         if i < 0 {
@@ -84,13 +467,109 @@ impl<T: Interpolateable + Debug + Clone> Curve<T> for DiscreetKeyFrameCurve<T> {
             return self.keys[i as usize].1.clone();
         }
     }
+    fn domain(&self) -> (f32, f32) {
+        (0.0, 1.0)
+    }
+}
+
+/// Translates `{ time, value }`, `[time, value]` or a float array `[time, value]` into a key,
+/// for any value type that translates from Pon
+pub fn translate_key<T: Clone>(pon: &Pon, context: &mut TranslateContext) -> Result<Key<T>, PonTranslateErr> where Pon: Translatable<T> {
+    match pon {
+        &Pon::Object(..) => {
+            let time: f32 = try!(pon.field_as::<f32>("time", context));
+            let value = try!(pon.field_as::<T>("value", context));
+            Ok(Key(time, value))
+        },
+        &Pon::Array(ref arr) if arr.len() == 2 => {
+            let time: f32 = try!(arr[0].translate::<f32>(context));
+            let value = try!(arr[1].translate::<T>(context));
+            Ok(Key(time, value))
+        },
+        &Pon::FloatArray(ref arr) if arr.len() == 2 => Ok(Key(arr[0], try!(Pon::Number(arr[1]).translate::<T>(context)))),
+        _ => {
+            Err(PonTranslateErr::MismatchType { expected: "Object or Array".to_string(), found: format!("{:?}", pon) })
+        }
+    }
+}
+
+impl Translatable<Key<f32>> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<Key<f32>, PonTranslateErr> {
+        translate_key(self, context)
+    }
+}
+
+impl Translatable<Key<Vector3<f32>>> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<Key<Vector3<f32>>, PonTranslateErr> {
+        // Read as an Animatable, so arrays work as well as vec3s
+        let Key(time, value) = try!(translate_key::<Animatable>(self, context));
+        if value.value.len() != 3 {
+            return Err(PonTranslateErr::MismatchType { expected: "three components".to_string(), found: format!("{:?}", value) });
+        }
+        Ok(Key(time, Vector3::new(value.value[0], value.value[1], value.value[2])))
+    }
+}
+
+impl Translatable<Box<Curve<Vector3<f32>>>> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<Box<Curve<Vector3<f32>>>, PonTranslateErr> {
+        self.as_typed(|&TypedPon { ref type_name, ref data }| -> Result<Box<Curve<Vector3<f32>>>, PonTranslateErr> {
+            match type_name.as_str() {
+                "fixed_value" => {
+                    let value = try!(data.translate::<Vector3<f32>>(context));
+                    Ok(Box::new(FixedValueCurve { value: value }))
+                },
+                "key_framed" => {
+                    let keys = try!(sort_keys(try!(translate_keys::<Key<Vector3<f32>>>(try!(data.field("keys")), context))));
+                    let easing = try!(data.field_as_or("easing", Easing::Linear, context));
+                    Ok(EasedKeyFrameCurve::new_boxed(LinearKeyFrameCurve::new(keys), easing))
+                },
+                s @ _ => Err(PonTranslateErr::UnrecognizedType(s.to_string()))
+            }
+        })
+    }
+}
+
+impl Translatable<Box<Curve<f32>>> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<Box<Curve<f32>>, PonTranslateErr> {
+        self.as_typed(|&TypedPon { ref type_name, ref data }| -> Result<Box<Curve<f32>>, PonTranslateErr> {
+            match type_name.as_str() {
+                "fixed_value" => {
+                    let value = try!(data.translate::<f32>(context));
+                    Ok(Box::new(FixedValueCurve { value: value }))
+                },
+                "key_framed" => {
+                    if has_bezier_handles(try!(data.field("keys"))) {
+                        let keys = try!(translate_keys::<BezierKey>(try!(data.field("keys")), context));
+                        let keys = try!(sort_keys(keys.into_iter().map(|key| Key(key.time, key)).collect()));
+                        return Ok(Box::new(CubicBezierCurve { keys: keys.into_iter().map(|key| key.1).collect() }));
+                    }
+                    let keys = try!(sort_keys(try!(translate_keys::<Key<f32>>(try!(data.field("keys")), context))));
+                    let easing = try!(data.field_as_or("easing", Easing::Linear, context));
+                    Ok(EasedKeyFrameCurve::new_boxed(LinearKeyFrameCurve::new(keys), easing))
+                },
+                "blend" => {
+                    let a = try!(data.field_as::<Box<Curve<f32>>>("a", context));
+                    let b = try!(data.field_as::<Box<Curve<f32>>>("b", context));
+                    let weight = try!(data.field_as_or("weight", 0.5, context));
+                    Ok(Box::new(BlendCurve { a: a, b: b, weight: weight }))
+                },
+                "quantize" => {
+                    let curve = try!(data.field_as::<Box<Curve<f32>>>("curve", context));
+                    let step = try!(data.field_as::<f32>("step", context));
+                    if step <= 0.0 {
+                        return Err(PonTranslateErr::InvalidValue { value: step.to_string() });
+                    }
+                    Ok(Box::new(QuantizeCurve { curve: curve, step: step }))
+                },
+                s @ _ => Err(PonTranslateErr::UnrecognizedType(s.to_string()))
+            }
+        })
+    }
 }
 
 #[test]
 fn test_key_frame_single() {
-    let kf = LinearKeyFrameCurve {
-        keys: vec![Key(0.0, 0.0), Key(1.0, 1.0)]
-    };
+    let kf = LinearKeyFrameCurve::new(vec![Key(0.0, 0.0), Key(1.0, 1.0)]);
     assert_eq!(kf.value(-0.1), 0.0);
     assert_eq!(kf.value(0.0), 0.0);
     assert_eq!(kf.value(0.5), 0.5);
@@ -100,9 +579,7 @@ fn test_key_frame_single() {
 
 #[test]
 fn test_key_frame_vector() {
-    let kf = LinearKeyFrameCurve {
-        keys: vec![Key(0.0, Vector2::new(0.0, 0.0)), Key(1.0, Vector2::new(1.0, 1.0))]
-    };
+    let kf = LinearKeyFrameCurve::new(vec![Key(0.0, Vector2::new(0.0, 0.0)), Key(1.0, Vector2::new(1.0, 1.0))]);
     assert_eq!(kf.value(-0.1), Vector2::new(0.0, 0.0));
     assert_eq!(kf.value(0.0), Vector2::new(0.0, 0.0));
     assert_eq!(kf.value(0.5), Vector2::new(0.5, 0.5));
@@ -110,11 +587,18 @@ fn test_key_frame_vector() {
     assert_eq!(kf.value(1.1), Vector2::new(1.0, 1.0));
 }
 
+#[test]
+fn test_vector3_curve() {
+    let curve: Box<Curve<Vector3<f32>>> = Pon::from_string(
+        "key_framed { keys: [[0.0, [0.0, 0.0, 0.0]], [1.0, [2.0, 4.0, 6.0]]] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(curve.value(0.5), Vector3::new(1.0, 2.0, 3.0));
+    assert_eq!(curve.value(1.0), Vector3::new(2.0, 4.0, 6.0));
+}
+
 #[test]
 fn test_key_frame_multi_keys() {
-    let kf = LinearKeyFrameCurve {
-        keys: vec![Key(0.0, 0.0), Key(10.0, 1.0), Key(20.0, 0.5), Key(21.0, 1.0), Key(22.0, 5.0)]
-    };
+    let kf = LinearKeyFrameCurve::new(vec![Key(0.0, 0.0), Key(10.0, 1.0), Key(20.0, 0.5), Key(21.0, 1.0), Key(22.0, 5.0)]);
     assert_eq!(kf.value(-0.1), 0.0);
     assert_eq!(kf.value(0.0), 0.0);
     assert_eq!(kf.value(0.5), 0.05);
@@ -122,3 +606,191 @@ fn test_key_frame_multi_keys() {
     assert_eq!(kf.value(21.5), 3.0);
     assert_eq!(kf.value(30.0), 5.0);
 }
+
+#[test]
+fn test_domain() {
+    assert_eq!(FixedValueCurve { value: 1.0 }.domain(), (0.0, 0.0));
+    assert_eq!(LinearKeyFrameCurve::new(vec![Key(0.5, 0.0), Key(2.0, 1.0), Key(3.0, 5.0)]).domain(), (0.5, 3.0));
+    assert_eq!(DiscreetKeyFrameCurve { keys: vec![Key(0.0, 0.0), Key(5.0, 1.0)] }.domain(), (0.0, 1.0));
+}
+
+#[test]
+fn test_value_clamped() {
+    let kf = LinearKeyFrameCurve::new(vec![Key(1.0, 2.0), Key(2.0, 4.0)]);
+    assert_eq!(kf.value_clamped(-5.0), 2.0);
+    assert_eq!(kf.value_clamped(0.999), 2.0);
+    assert_eq!(kf.value_clamped(1.5), 3.0);
+    assert_eq!(kf.value_clamped(2.001), 4.0);
+    assert_eq!(kf.value_clamped(10.0), 4.0);
+    assert_eq!(FixedValueCurve { value: 7.0 }.value_clamped(3.0), 7.0);
+}
+
+#[cfg(test)]
+fn brute_force_segment(keys: &[Key<f32>], time: f32) -> (f32, f32, f32) {
+    let before = keys.iter().filter(|k| k.0 <= time).last();
+    let after = keys.iter().filter(|k| k.0 > time).next();
+    match (before, after) {
+        (Some(a), Some(b)) => (a.1, b.1, (time - a.0) / (b.0 - a.0)),
+        (Some(a), None) => (a.1, a.1, 0.0),
+        _ => (keys[0].1, keys[0].1, 0.0)
+    }
+}
+
+#[test]
+fn test_segment_matches_brute_force() {
+    let keys: Vec<Key<f32>> = (0..1000).map(|i| Key(i as f32 * 0.5, ((i * 7919) % 100) as f32)).collect();
+    let kf = LinearKeyFrameCurve::new(keys.clone());
+    for i in 0..2200 {
+        let time = i as f32 * 0.23 - 10.0;
+        let (a, b, p) = kf.segment(time);
+        assert_eq!((*a, *b, p), brute_force_segment(&keys, time));
+    }
+    assert_eq!(kf.segment(2.0), (&keys[4].1, &keys[5].1, 0.0));
+}
+
+#[test]
+fn test_segment_cache() {
+    let keys: Vec<Key<f32>> = (0..200).map(|i| Key(i as f32 * 0.5, ((i * 31) % 17) as f32)).collect();
+    let kf = LinearKeyFrameCurve::new(keys.clone());
+    // Sequential playback, the way tracks are usually sampled
+    for i in 0..2000 {
+        let time = i as f32 * 0.05 - 1.0;
+        let (a, b, p) = kf.segment(time);
+        assert_eq!((*a, *b, p), brute_force_segment(&keys, time));
+    }
+    // Random access, which has to miss the cache and still find the right segment
+    let mut seed: u32 = 12345;
+    for _ in 0..2000 {
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        let time = (seed % 11000) as f32 * 0.01 - 5.0;
+        let (a, b, p) = kf.segment(time);
+        assert_eq!((*a, *b, p), brute_force_segment(&keys, time));
+    }
+}
+
+#[test]
+fn test_precomputed_segments() {
+    let keys = vec![Key(0.0, 1.0), Key(0.75, -2.0), Key(3.0, 5.0), Key(3.1, 0.0), Key(10.0, 4.0)];
+    let kf = LinearKeyFrameCurve::new(keys.clone());
+    for i in 0..250 {
+        let time = i as f32 * 0.05 - 1.0;
+        let (a, b, p) = brute_force_segment(&keys, time);
+        assert!((kf.value(time) - (a + (b - a) * p)).abs() < 0.0001);
+    }
+}
+
+#[test]
+fn test_unsorted_keys() {
+    let curve: Box<Curve<f32>> = Pon::from_string("key_framed { keys: [[1.0, 2.0], [0.0, 0.0], [2.0, 0.0]] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(curve.domain(), (0.0, 2.0));
+    assert_eq!(curve.value(0.5), 1.0);
+    assert_eq!(curve.value(1.5), 1.0);
+}
+
+#[test]
+fn test_duplicate_key_times() {
+    let res: Result<Box<Curve<f32>>, PonTranslateErr> = Pon::from_string("key_framed { keys: [[0.0, 0.0], [1.0, 2.0], [1.0, 3.0]] }")
+        .unwrap().translate(&mut TranslateContext::empty());
+    match res {
+        Err(err) => assert!(err.to_string().contains("more than one key at time 1")),
+        Ok(_) => panic!("expected duplicate key times to be rejected")
+    }
+}
+
+#[test]
+fn test_add_keys_out_of_order() {
+    let mut curve = LinearKeyFrameCurve::new(vec![]);
+    curve.add_key(Key(2.0, 4.0));
+    curve.add_key(Key(0.0, 0.0));
+    curve.add_key(Key(1.0, 1.0));
+    assert_eq!(curve.keys().iter().map(|key| key.0).collect::<Vec<f32>>(), vec![0.0, 1.0, 2.0]);
+    assert_eq!(curve.value(0.5), 0.5);
+    assert_eq!(curve.value(1.5), 2.5);
+    curve.add_key(Key(1.0, 2.0));
+    assert_eq!(curve.keys().len(), 3);
+    assert_eq!(curve.value(0.5), 1.0);
+}
+
+#[test]
+fn test_remove_middle_key() {
+    let mut curve = LinearKeyFrameCurve::new(vec![Key(0.0, 0.0), Key(1.0, 3.0), Key(2.0, 2.0)]);
+    assert_eq!(curve.value(1.0), 3.0);
+    assert_eq!(curve.remove_key_at(1.0), Some(Key(1.0, 3.0)));
+    assert_eq!(curve.remove_key_at(1.5), None);
+    assert_eq!(curve.value(1.0), 1.0);
+    assert_eq!(curve.value(1.5), 1.5);
+    curve.clear_keys();
+    assert_eq!(curve.keys().len(), 0);
+}
+
+#[test]
+fn test_samples() {
+    let curve: Box<Curve<f32>> = Box::new(LinearKeyFrameCurve::new(vec![Key(0.0, 0.0), Key(1.0, 2.0)]));
+    let samples: Vec<(f32, f32)> = curve.samples(0.0, 1.0, 5).collect();
+    assert_eq!(samples, vec![(0.0, 0.0), (0.25, 0.5), (0.5, 1.0), (0.75, 1.5), (1.0, 2.0)]);
+    assert_eq!(curve.samples(0.0, 1.0, 5).len(), 5);
+    assert_eq!(curve.samples(0.5, 1.0, 1).collect::<Vec<(f32, f32)>>(), vec![(0.5, 1.0)]);
+    assert_eq!(curve.samples(0.0, 1.0, 0).count(), 0);
+}
+
+#[test]
+fn test_keys_at_same_time() {
+    let curve = LinearKeyFrameCurve::new(vec![Key(0.0, 0.0), Key(1.0, 1.0), Key(1.0, 3.0), Key(2.0, 0.0)]);
+    for i in 0..21 {
+        assert!(curve.value(i as f32 * 0.1).is_finite());
+    }
+    assert_eq!(curve.value(1.0), 3.0);
+    let curve = LinearKeyFrameCurve::new(vec![Key(1.0, 1.0), Key(1.0, 3.0)]);
+    assert!(curve.value(1.0).is_finite());
+    assert!(curve.value(0.5).is_finite());
+}
+
+#[test]
+fn test_set_keys() {
+    let mut curve = LinearKeyFrameCurve::new(vec![Key(0.0, 0.0), Key(1.0, 1.0)]);
+    assert_eq!(curve.value(0.5), 0.5);
+    curve.set_keys(vec![Key(0.0, 0.0), Key(2.0, 4.0)]);
+    assert_eq!(curve.keys(), &[Key(0.0, 0.0), Key(2.0, 4.0)][..]);
+    assert_eq!(curve.value(0.5), 1.0);
+}
+
+#[test]
+fn test_cyclic_before_first_key() {
+    let mut curve = LinearKeyFrameCurve::new(vec![Key(0.5, 0.0), Key(1.5, 1.0)]);
+    assert_eq!(curve.value(0.0), 0.0);
+    curve.set_cyclic(Some(2.0));
+    // The wrap segment runs from the last key at -0.5 to the first key at 0.5
+    assert_eq!(curve.value(0.0), 0.5);
+    assert_eq!(curve.value(2.0), 0.5);
+    assert_eq!(curve.value(1.0), 0.5);
+}
+
+#[test]
+fn test_cyclic_repeats() {
+    let mut curve = LinearKeyFrameCurve::new(vec![Key(0.5, 0.0), Key(1.5, 1.0)]);
+    curve.set_cyclic(Some(2.0));
+    for &(time, value) in [(1.0, 0.5), (2.0, 0.5), (3.5, 1.0)].iter() {
+        assert_eq!(curve.value(time), value);
+        assert_eq!(curve.value(time + 4.0), value);
+        assert_eq!(curve.value(time - 6.0), value);
+    }
+}
+
+#[test]
+fn test_clear_then_add_key() {
+    let mut curve = LinearKeyFrameCurve::new(vec![Key(0.0, 0.0), Key(1.0, 2.0)]);
+    curve.clear_keys();
+    assert!(curve.is_empty());
+    curve.add_key(Key(1.0, 3.0));
+    assert!(!curve.is_empty());
+    assert_eq!(curve.value(0.5), 3.0);
+}
+
+#[test]
+#[should_panic(expected = "without keys")]
+fn test_sample_after_clear_keys() {
+    let mut curve = LinearKeyFrameCurve::new(vec![Key(0.0, 0.0), Key(1.0, 2.0)]);
+    curve.clear_keys();
+    curve.value(0.5);
+}
@@ -0,0 +1,379 @@
+
+use std::cmp;
+use cgmath::Vector3;
+use curve::*;
+use animatable::*;
+#[cfg(test)]
+use pyramid::pon::*;
+
+/// Numerical derivative of a curve, using central differences. One-sided differences
+/// are used close to the ends of the curve's domain, so it's never sampled outside of it.
+#[derive(Debug)]
+pub struct DerivativeCurve {
+    pub curve: Box<Curve<f32>>,
+    pub epsilon: f32
+}
+
+impl DerivativeCurve {
+    pub fn new(curve: Box<Curve<f32>>) -> DerivativeCurve {
+        DerivativeCurve {
+            curve: curve,
+            epsilon: 0.001
+        }
+    }
+}
+
+impl Curve<f32> for DerivativeCurve {
+    fn value(&self, time: f32) -> f32 {
+        let e = self.epsilon;
+        let (start, end) = self.curve.domain();
+        if end - start >= 2.0 * e {
+            if time - e < start {
+                return (self.curve.value(time + e) - self.curve.value(time)) / e;
+            } else if time + e > end {
+                return (self.curve.value(time) - self.curve.value(time - e)) / e;
+            }
+        }
+        (self.curve.value(time + e) - self.curve.value(time - e)) / (2.0 * e)
+    }
+    fn domain(&self) -> (f32, f32) {
+        self.curve.domain()
+    }
+}
+
+/// Integral of a curve from 0 to t, using the trapezoidal rule.
+#[derive(Debug)]
+pub struct IntegralCurve {
+    pub curve: Box<Curve<f32>>,
+    /// Number of trapezoids used when integrating directly
+    pub steps: usize,
+    baked: Option<BakedIntegral>
+}
+
+#[derive(Debug)]
+struct BakedIntegral {
+    length: f32,
+    values: Vec<f32>
+}
+
+impl IntegralCurve {
+    pub fn new(curve: Box<Curve<f32>>) -> IntegralCurve {
+        IntegralCurve {
+            curve: curve,
+            steps: 100,
+            baked: None
+        }
+    }
+    /// Precomputes the integral over 0..length into a table of resolution + 1 samples, with a
+    /// resolution of at least 1. Times outside of the table are integrated directly, and nothing is
+    /// precomputed if length isn't positive.
+    pub fn baked(curve: Box<Curve<f32>>, length: f32, resolution: usize) -> IntegralCurve {
+        if !(length > 0.0) {
+            return IntegralCurve::new(curve);
+        }
+        let resolution = cmp::max(resolution, 1);
+        let mut values = vec![0.0];
+        let step = length / resolution as f32;
+        let mut acc = 0.0;
+        for i in 0..resolution {
+            let t = i as f32 * step;
+            acc += (curve.value(t) + curve.value(t + step)) * 0.5 * step;
+            values.push(acc);
+        }
+        IntegralCurve {
+            curve: curve,
+            steps: 100,
+            baked: Some(BakedIntegral { length: length, values: values })
+        }
+    }
+    fn integrate(&self, time: f32) -> f32 {
+        let step = time / self.steps as f32;
+        let mut acc = 0.0;
+        for i in 0..self.steps {
+            let t = i as f32 * step;
+            acc += (self.curve.value(t) + self.curve.value(t + step)) * 0.5 * step;
+        }
+        acc
+    }
+}
+
+impl Curve<f32> for IntegralCurve {
+    fn value(&self, time: f32) -> f32 {
+        if let Some(ref baked) = self.baked {
+            if time >= 0.0 && time <= baked.length {
+                let segments = baked.values.len() - 1;
+                let x = time / baked.length * segments as f32;
+                let i = cmp::min(x as usize, segments - 1);
+                let p = x - i as f32;
+                return baked.values[i] * (1.0 - p) + baked.values[i + 1] * p;
+            }
+        }
+        self.integrate(time)
+    }
+    fn domain(&self) -> (f32, f32) {
+        self.curve.domain()
+    }
+}
+
+/// Blends between two curves, where a weight of 0 gives `a` and 1 gives `b`.
+#[derive(Debug)]
+pub struct BlendCurve {
+    pub a: Box<Curve<f32>>,
+    pub b: Box<Curve<f32>>,
+    pub weight: f32
+}
+
+impl Curve<f32> for BlendCurve {
+    fn value(&self, time: f32) -> f32 {
+        Interpolateable::interpolate(&self.a.value(time), &self.b.value(time), &self.weight)
+    }
+    fn domain(&self) -> (f32, f32) {
+        let (a, b) = (self.a.domain(), self.b.domain());
+        (a.0.min(b.0), a.1.max(b.1))
+    }
+}
+
+/// Snaps the value of a curve to the nearest multiple of step.
+#[derive(Debug)]
+pub struct QuantizeCurve {
+    pub curve: Box<Curve<f32>>,
+    pub step: f32
+}
+
+impl Curve<f32> for QuantizeCurve {
+    fn value(&self, time: f32) -> f32 {
+        (self.curve.value(time) / self.step).round() * self.step
+    }
+    fn domain(&self) -> (f32, f32) {
+        self.curve.domain()
+    }
+}
+
+/// A curve sampled into a table of evenly spaced values, for curves that are expensive to evaluate.
+/// Times outside of the domain get the value at the closest end.
+#[derive(PartialEq, Debug)]
+pub struct BakedCurve {
+    domain: (f32, f32),
+    values: Vec<f32>
+}
+
+impl BakedCurve {
+    /// Samples curve at `samples` evenly spaced times (at least two) covering domain, ends included
+    pub fn bake(curve: Box<Curve<f32>>, samples: usize, domain: (f32, f32)) -> BakedCurve {
+        let samples = cmp::max(samples, 2);
+        let step = (domain.1 - domain.0) / (samples - 1) as f32;
+        BakedCurve {
+            domain: domain,
+            values: (0..samples).map(|i| curve.value(domain.0 + i as f32 * step)).collect()
+        }
+    }
+}
+
+impl Curve<f32> for BakedCurve {
+    fn value(&self, time: f32) -> f32 {
+        let (start, end) = self.domain;
+        if end <= start {
+            return self.values[0];
+        }
+        let segments = self.values.len() - 1;
+        let x = (time.max(start).min(end) - start) / (end - start) * segments as f32;
+        let i = cmp::min(x as usize, segments - 1);
+        let p = x - i as f32;
+        self.values[i] * (1.0 - p) + self.values[i + 1] * p
+    }
+    fn domain(&self) -> (f32, f32) {
+        self.domain
+    }
+}
+
+fn distance(a: &Vector3<f32>, b: &Vector3<f32>) -> f32 {
+    let (x, y, z) = (b.x - a.x, b.y - a.y, b.z - a.z);
+    (x * x + y * y + z * z).sqrt()
+}
+
+/// Moves along a path at constant speed. The value at t is the point at fraction t (0 to 1) of the
+/// way along the path, however the keys of the path are spaced in time. The path is measured at
+/// evenly spaced times when the curve is created, and lengths are interpolated in between.
+#[derive(Debug)]
+pub struct ArcLengthCurve {
+    pub curve: Box<Curve<Vector3<f32>>>,
+    /// Times into the curve, and the distance along the path at each of them
+    table: Vec<(f32, f32)>
+}
+
+impl ArcLengthCurve {
+    pub fn new(curve: Box<Curve<Vector3<f32>>>) -> ArcLengthCurve {
+        ArcLengthCurve::with_samples(curve, 256)
+    }
+    /// Measures the path at `samples` evenly spaced times (at least two) covering the curve's domain
+    pub fn with_samples(curve: Box<Curve<Vector3<f32>>>, samples: usize) -> ArcLengthCurve {
+        let samples = cmp::max(samples, 2);
+        let (start, end) = curve.domain();
+        let step = (end - start) / (samples - 1) as f32;
+        let mut table = Vec::with_capacity(samples);
+        let mut last = curve.value(start);
+        let mut length = 0.0;
+        table.push((start, 0.0));
+        for i in 1..samples {
+            let time = start + i as f32 * step;
+            let point = curve.value(time);
+            length += distance(&last, &point);
+            table.push((time, length));
+            last = point;
+        }
+        ArcLengthCurve {
+            curve: curve,
+            table: table
+        }
+    }
+    /// Total length of the path
+    pub fn length(&self) -> f32 {
+        self.table[self.table.len() - 1].1
+    }
+}
+
+impl Curve<Vector3<f32>> for ArcLengthCurve {
+    fn value(&self, t: f32) -> Vector3<f32> {
+        let length = self.length();
+        if length <= 0.0 {
+            return self.curve.value(self.table[0].0);
+        }
+        let target = t.max(0.0).min(1.0) * length;
+        // The first entry at or past the target distance
+        let i = match self.table.binary_search_by(|entry| if entry.1 < target { cmp::Ordering::Less } else { cmp::Ordering::Greater }) {
+            Ok(i) => i,
+            Err(i) => i
+        };
+        let i = cmp::max(cmp::min(i, self.table.len() - 1), 1);
+        let ((t0, d0), (t1, d1)) = (self.table[i - 1], self.table[i]);
+        let p = if d1 > d0 { (target - d0) / (d1 - d0) } else { 0.0 };
+        self.curve.value(t0 + (t1 - t0) * p)
+    }
+    fn domain(&self) -> (f32, f32) {
+        (0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+fn assert_near(a: f32, b: f32) {
+    assert!((a - b).abs() < 0.001, "{} != {}", a, b);
+}
+
+#[test]
+fn test_derivative_of_ramp() {
+    let curve = DerivativeCurve::new(Box::new(LinearKeyFrameCurve::new(vec![Key(0.0, 0.0), Key(2.0, 4.0)])));
+    assert_near(curve.value(0.0), 2.0);
+    assert_near(curve.value(0.5), 2.0);
+    assert_near(curve.value(1.3), 2.0);
+    assert_near(curve.value(2.0), 2.0);
+}
+
+#[test]
+fn test_integral_of_constant() {
+    let curve = IntegralCurve::new(Box::new(FixedValueCurve { value: 3.0 }));
+    assert_near(curve.value(0.0), 0.0);
+    assert_near(curve.value(0.5), 1.5);
+    assert_near(curve.value(2.0), 6.0);
+}
+
+#[test]
+fn test_integral_of_constant_baked() {
+    let curve = IntegralCurve::baked(Box::new(FixedValueCurve { value: 3.0 }), 2.0, 16);
+    assert_near(curve.value(0.0), 0.0);
+    assert_near(curve.value(0.3), 0.9);
+    assert_near(curve.value(2.0), 6.0);
+    assert_near(curve.value(3.0), 9.0);
+}
+
+#[test]
+fn test_integral_baked_degenerate() {
+    let curve = IntegralCurve::baked(Box::new(FixedValueCurve { value: 3.0 }), 2.0, 0);
+    assert_near(curve.value(1.0), 3.0);
+    let curve = IntegralCurve::baked(Box::new(FixedValueCurve { value: 3.0 }), 0.0, 16);
+    assert_near(curve.value(0.0), 0.0);
+    assert_near(curve.value(1.0), 3.0);
+}
+
+#[test]
+fn test_blend_weights() {
+    let curve: Box<Curve<f32>> = Pon::from_string(
+        "blend { a: key_framed { keys: [[0.0, 0.0], [1.0, 1.0]] }, b: fixed_value 4.0, weight: 0.0 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_near(curve.value(0.5), 0.5);
+    let curve = BlendCurve {
+        a: Box::new(LinearKeyFrameCurve::new(vec![Key(0.0, 0.0), Key(1.0, 1.0)])),
+        b: Box::new(FixedValueCurve { value: 4.0 }),
+        weight: 1.0
+    };
+    assert_near(curve.value(0.5), 4.0);
+}
+
+#[test]
+fn test_blend_midway() {
+    let curve: Box<Curve<f32>> = Pon::from_string(
+        "blend { a: fixed_value 2.0, b: fixed_value 4.0, weight: 0.5 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_near(curve.value(0.0), 3.0);
+}
+
+#[test]
+fn test_quantize_steps() {
+    let curve: Box<Curve<f32>> = Pon::from_string(
+        "quantize { curve: key_framed { keys: [[0.0, 0.0], [1.0, 1.0]] }, step: 0.25 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    for i in 0..100 {
+        let v = curve.value(i as f32 * 0.01);
+        assert_eq!(v, (v / 0.25).round() * 0.25);
+    }
+    assert_eq!(curve.value(0.1), 0.0);
+    assert_eq!(curve.value(0.2), 0.25);
+    assert_eq!(curve.value(0.6), 0.5);
+}
+
+#[test]
+fn test_domain_of_wrapped_curves() {
+    let ramp = || Box::new(LinearKeyFrameCurve::new(vec![Key(1.0, 0.0), Key(2.0, 4.0)]));
+    assert_eq!(DerivativeCurve::new(ramp()).domain(), (1.0, 2.0));
+    assert_eq!(IntegralCurve::new(ramp()).domain(), (1.0, 2.0));
+    assert_eq!(QuantizeCurve { curve: ramp(), step: 1.0 }.domain(), (1.0, 2.0));
+    let blend = BlendCurve {
+        a: ramp(),
+        b: Box::new(LinearKeyFrameCurve::new(vec![Key(0.0, 0.0), Key(1.5, 4.0)])),
+        weight: 0.5
+    };
+    assert_eq!(blend.domain(), (0.0, 2.0));
+}
+
+#[test]
+fn test_baked_curve() {
+    let source = || Box::new(LinearKeyFrameCurve::new(vec![Key(0.0, 0.0), Key(1.0, 3.0), Key(2.0, -1.0)]));
+    let baked = BakedCurve::bake(source(), 41, (0.0, 2.0));
+    let reference = source();
+    assert_eq!(baked.domain(), (0.0, 2.0));
+    for i in 0..200 {
+        let t = i as f32 * 0.01;
+        assert_near(baked.value(t), reference.value(t));
+    }
+    assert_near(baked.value(-1.0), 0.0);
+    assert_near(baked.value(5.0), -1.0);
+}
+
+#[test]
+fn test_arc_length_constant_speed() {
+    // Three quarters of the path are covered in the first tenth of the time
+    let path = LinearKeyFrameCurve::new(vec![
+        Key(0.0, Vector3::new(0.0, 0.0, 0.0)),
+        Key(0.1, Vector3::new(1.8, 2.4, 0.0)),
+        Key(1.0, Vector3::new(2.4, 3.2, 0.0))
+    ]);
+    let curve = ArcLengthCurve::new(Box::new(path));
+    assert_near(curve.length(), 4.0);
+    let mut last = curve.value(0.0);
+    for i in 1..21 {
+        let point = curve.value(i as f32 * 0.05);
+        assert_near(distance(&last, &point), 0.2);
+        last = point;
+    }
+    assert_near(curve.value(0.5).x, 1.2);
+    assert_near(curve.value(1.0).y, 3.2);
+}
@@ -1,4 +1,12 @@
 
+use std::cmp;
+use std::cmp::Ordering;
+#[cfg(test)]
+use std::any::Any;
+#[cfg(test)]
+use pyramid::document::*;
+#[cfg(test)]
+use track_set::*;
 use time::*;
 
 use curve::*;
@@ -6,21 +14,12 @@ use track::*;
 use pyramid::pon::*;
 use cgmath::*;
 use animatable::*;
+use easing::*;
+use interpolation::*;
+use error::*;
+use event_track::*;
 
-#[derive(PartialEq, Debug, Clone)]
-pub enum Loop {
-    Forever,
-    Once
-}
-
-#[derive(PartialEq, Debug, Clone)]
-pub enum CurveTime {
-    /// The curve is expected to have keys between 0 and 1
-    Relative,
-    /// The curve is expected to have keys between 0 and duration
-    Absolute
-}
-
+pub use common::{Loop, CurveTime};
 
 #[derive(Debug)]
 pub struct CurveTrack {
@@ -29,7 +28,78 @@ pub struct CurveTrack {
     pub property: NamedPropRef,
     pub loop_type: Loop,
     pub duration: Duration,
-    pub curve_time: CurveTime
+    pub curve_time: CurveTime,
+    /// Fraction of duration (0 to 1) to shift the start of the loop cycle by
+    pub phase: f32,
+    /// Time to hold the end value for before starting the next loop cycle
+    pub loop_gap: Duration,
+    /// Playback speed, where 2.0 plays the curve twice as fast
+    pub speed: f32,
+    /// Plays the curve from the end to the start
+    pub reversed: bool,
+    /// Jumps from key to key instead of blending between them, for properties
+    /// like frame indices that can't take values in between
+    pub discrete: bool
+}
+
+/// A CurveTrack in a form that can be serialized. The property is on the animated entity.
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Debug, Clone)]
+pub struct CurveTrackData {
+    pub curve: CurveData<Animatable>,
+    pub offset_ms: i64,
+    pub property: String,
+    pub loop_type: Loop,
+    pub duration_ms: i64,
+    pub curve_time: CurveTime,
+    pub phase: f32,
+    pub loop_gap_ms: i64,
+    pub speed: f32,
+    pub reversed: bool,
+    pub discrete: bool
+}
+
+impl CurveTrack {
+    /// The track as CurveTrackData, or None if the curve isn't one of the built in key frame
+    /// curves or the property isn't on the animated entity
+    pub fn to_data(&self) -> Option<CurveTrackData> {
+        match self.property.entity_path {
+            EntityPath::This => {},
+            _ => return None
+        }
+        let curve = match self.curve.to_data() {
+            Some(curve) => curve,
+            None => return None
+        };
+        Some(CurveTrackData {
+            curve: curve,
+            offset_ms: self.offset.num_milliseconds(),
+            property: self.property.property_key.clone(),
+            loop_type: self.loop_type.clone(),
+            duration_ms: self.duration.num_milliseconds(),
+            curve_time: self.curve_time.clone(),
+            phase: self.phase,
+            loop_gap_ms: self.loop_gap.num_milliseconds(),
+            speed: self.speed,
+            reversed: self.reversed,
+            discrete: self.discrete
+        })
+    }
+    pub fn from_data(data: CurveTrackData) -> CurveTrack {
+        CurveTrack {
+            curve: data.curve.into_curve(),
+            offset: Duration::milliseconds(data.offset_ms),
+            property: NamedPropRef::new(EntityPath::This, &data.property),
+            loop_type: data.loop_type,
+            duration: Duration::milliseconds(data.duration_ms),
+            curve_time: data.curve_time,
+            phase: data.phase,
+            loop_gap: Duration::milliseconds(data.loop_gap_ms),
+            speed: data.speed,
+            reversed: data.reversed,
+            discrete: data.discrete
+        }
+    }
 }
 
 impl CurveTrack {
@@ -40,69 +110,406 @@ impl CurveTrack {
             property: property,
             loop_type: Loop::Forever,
             duration: Duration::weeks(1),
-            curve_time: CurveTime::Absolute
+            curve_time: CurveTime::Absolute,
+            phase: 0.0,
+            loop_gap: Duration::zero(),
+            speed: 1.0,
+            reversed: false,
+            discrete: false
         }
     }
 }
 
-impl Track for CurveTrack {
-    fn value_at(&self, time: Duration) -> Vec<(NamedPropRef, Animatable)> {
+/// Chained construction of a CurveTrack, starting from the same defaults as new_fixed_value
+/// with a constant zero curve on `this`
+#[derive(Debug)]
+pub struct CurveTrackBuilder {
+    track: CurveTrack
+}
+
+impl CurveTrackBuilder {
+    pub fn new() -> CurveTrackBuilder {
+        CurveTrackBuilder {
+            track: CurveTrack::new_fixed_value(NamedPropRef::new(EntityPath::This, ""), Animatable::new_float(0.0))
+        }
+    }
+    pub fn property(mut self, property: NamedPropRef) -> CurveTrackBuilder {
+        self.track.property = property;
+        self
+    }
+    pub fn curve(mut self, curve: Box<Curve<Animatable>>) -> CurveTrackBuilder {
+        self.track.curve = curve;
+        self
+    }
+    /// Linearly interpolates between keys, which are sorted by time
+    pub fn linear_keys(self, mut keys: Vec<Key<Animatable>>) -> CurveTrackBuilder {
+        keys.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        self.curve(Box::new(LinearKeyFrameCurve::new(keys)))
+    }
+    pub fn fixed_value(self, value: Animatable) -> CurveTrackBuilder {
+        self.curve(Box::new(FixedValueCurve { value: value }))
+    }
+    pub fn offset(mut self, offset: Duration) -> CurveTrackBuilder {
+        self.track.offset = offset;
+        self
+    }
+    pub fn loop_type(mut self, loop_type: Loop) -> CurveTrackBuilder {
+        self.track.loop_type = loop_type;
+        self
+    }
+    pub fn duration(mut self, duration: Duration) -> CurveTrackBuilder {
+        self.track.duration = duration;
+        self
+    }
+    pub fn curve_time(mut self, curve_time: CurveTime) -> CurveTrackBuilder {
+        self.track.curve_time = curve_time;
+        self
+    }
+    /// Panics if phase isn't from 0 up to 1
+    pub fn phase(mut self, phase: f32) -> CurveTrackBuilder {
+        assert!(phase >= 0.0 && phase < 1.0, "phase must be from 0 up to 1, got {}", phase);
+        self.track.phase = phase;
+        self
+    }
+    /// Panics if loop_gap is negative, since loop cycles would overlap
+    pub fn loop_gap(mut self, loop_gap: Duration) -> CurveTrackBuilder {
+        assert!(loop_gap >= Duration::zero(), "loop_gap can't be negative, got {}ms", loop_gap.num_milliseconds());
+        self.track.loop_gap = loop_gap;
+        self
+    }
+    /// Panics if speed isn't positive, since the track would never end
+    pub fn speed(mut self, speed: f32) -> CurveTrackBuilder {
+        assert!(speed > 0.0, "speed must be positive, got {}", speed);
+        self.track.speed = speed;
+        self
+    }
+    pub fn reversed(mut self, reversed: bool) -> CurveTrackBuilder {
+        self.track.reversed = reversed;
+        self
+    }
+    pub fn discrete(mut self, discrete: bool) -> CurveTrackBuilder {
+        self.track.discrete = discrete;
+        self
+    }
+    pub fn build(self) -> CurveTrack {
+        self.track
+    }
+}
+
+impl CurveTrack {
+    /// Time since the track started with speed and phase applied, or None before it starts
+    fn playback_time(&self, time: Duration) -> Option<Duration> {
         let time = time - self.offset;
-        let time = if time > self.duration {
-            if self.loop_type == Loop::Forever {
-                Duration::milliseconds(time.num_milliseconds() % self.duration.num_milliseconds())
-            } else {
-                return vec![]
+        if time < Duration::zero() {
+            return None;
+        }
+        if self.speed == 1.0 && self.phase == 0.0 {
+            return Some(time);
+        }
+        // f64, since f32 stops resolving milliseconds after a few hours
+        let duration_ms = self.duration.num_milliseconds() as f64;
+        Some(Duration::milliseconds((time.num_milliseconds() as f64 * self.speed as f64 + self.phase as f64 * duration_ms) as i64))
+    }
+    /// Which loop cycle time falls in, counting from 0, or None if the track isn't playing.
+    /// Tracks that play once, including hold, stay in cycle 0.
+    pub fn cycle_at(&self, time: Duration) -> Option<u32> {
+        if self.local_time(time).is_none() {
+            return None;
+        }
+        let time_ms = self.playback_time(time).unwrap().num_milliseconds();
+        let cycle = (time_ms / (self.duration.num_milliseconds() + self.loop_gap.num_milliseconds())) as u32;
+        match self.loop_type {
+            Loop::Once | Loop::Hold | Loop::OnceThenHoldOneFrame => Some(0),
+            Loop::Forever | Loop::PingPong => Some(cycle),
+            // The very end of the last cycle would otherwise count as the start of the next
+            Loop::Count(n) => Some(cmp::min(cycle, n.saturating_sub(1)))
+        }
+    }
+    /// The time into the curve at time, or None if the track isn't playing
+    pub fn local_time(&self, time: Duration) -> Option<Duration> {
+        if self.loop_type == Loop::Count(0) {
+            return None;
+        }
+        let duration_ms = self.duration.num_milliseconds();
+        let time = match self.playback_time(time) {
+            Some(time) => time,
+            None => return None
+        };
+        if time <= self.duration {
+            return Some(time);
+        }
+        let time_ms = time.num_milliseconds();
+        let cycle_ms = duration_ms + self.loop_gap.num_milliseconds();
+        let cycle = time_ms / cycle_ms;
+        // During the gap after a cycle the end of the cycle is held
+        let local = Duration::milliseconds(cmp::min(time_ms % cycle_ms, duration_ms));
+        match self.loop_type {
+            Loop::Forever => Some(local),
+            Loop::Once | Loop::OnceThenHoldOneFrame => None,
+            Loop::Hold => Some(self.duration),
+            Loop::PingPong => {
+                if cycle % 2 == 1 {
+                    Some(self.duration - local)
+                } else {
+                    Some(local)
+                }
+            },
+            Loop::Count(n) => {
+                let end_ms = (n as i64 - 1) * cycle_ms + duration_ms;
+                if time_ms > end_ms {
+                    None
+                } else if time_ms == end_ms {
+                    Some(self.duration)
+                } else {
+                    Some(local)
+                }
             }
-        } else {
-            time
+        }
+    }
+    /// True if the curve is a fixed value, so sampling it skips mapping time into the curve
+    pub fn is_fixed_value(&self) -> bool {
+        self.curve.is_constant()
+    }
+    /// Value of the curve at time, or None when the track isn't playing
+    fn sample(&self, time: Duration) -> Option<Animatable> {
+        // A constant curve has the same value everywhere, so only whether the track is playing matters
+        if self.is_fixed_value() {
+            let playing = time >= self.offset && self.loop_type != Loop::Count(0) && !self.is_finished(time);
+            return if playing { Some(self.curve.value(0.0)) } else { None };
+        }
+        let time = match self.local_time(time) {
+            Some(time) if self.reversed => self.duration - time,
+            Some(time) => time,
+            None => return None
         };
-        let time = match self.curve_time {
-            CurveTime::Absolute => time.num_milliseconds() as f32 / 1000.0,
-            CurveTime::Relative => time.num_milliseconds() as f32 / self.duration.num_milliseconds() as f32
+        Some(self.curve_value(self.curve_time_at(time)))
+    }
+    /// Maps time into the curve into the curve's own time unit. Loops keep the time into the curve
+    /// within the duration, the clamp makes sure relative and percent curves are never sampled
+    /// outside of their range.
+    fn curve_time_at(&self, time: Duration) -> f32 {
+        to_curve_time(&self.curve_time, time, self.duration)
+    }
+    /// Writes value to out in place of the value from an earlier sample, see Track::value_at_reusing
+    fn write_reusing(&self, value: Option<Animatable>, out: &mut Vec<(NamedPropRef, Animatable)>) {
+        match value {
+            Some(value) => {
+                if out.len() == 1 && out[0].0 == self.property {
+                    out[0].1 = value;
+                } else {
+                    out.clear();
+                    out.push((self.property.clone(), value));
+                }
+            },
+            None => out.clear()
+        }
+    }
+    /// Value of the curve where playback ends
+    fn end_value(&self) -> Animatable {
+        let time = if self.reversed { Duration::zero() } else { self.duration };
+        self.curve_value(self.curve_time_at(time))
+    }
+    fn curve_value(&self, time: f32) -> Animatable {
+        if self.discrete { self.curve.stepped_value(time) } else { self.curve.value(time) }
+    }
+    /// The smallest and largest value of each component over the curve's duration, for scaling plots.
+    /// The curve is sampled at its keys and at evenly spaced times in between, so curves that
+    /// overshoot between their keys may go slightly outside of the range.
+    pub fn value_range(&self) -> Vec<(f32, f32)> {
+        let (start, end) = (self.curve_time_at(Duration::zero()), self.curve_time_at(self.duration));
+        let samples = 100;
+        let mut times: Vec<f32> = (0..samples + 1).map(|i| start + (end - start) * i as f32 / samples as f32).collect();
+        times.extend(self.curve.key_times().into_iter().filter(|&t| t >= start && t <= end));
+        let mut range: Vec<(f32, f32)> = vec![];
+        for time in times {
+            let value = self.curve_value(time);
+            for (i, &v) in value.value.iter().enumerate() {
+                if i < range.len() {
+                    range[i] = (range[i].0.min(v), range[i].1.max(v));
+                } else {
+                    range.push((v, v));
+                }
+            }
+        }
+        range
+    }
+    /// True if everything but the curve and property is as new_fixed_value sets it
+    fn has_fixed_value_defaults(&self) -> bool {
+        self.offset == Duration::zero() && self.loop_type == Loop::Forever && self.duration == Duration::weeks(1) &&
+            self.curve_time == CurveTime::Absolute && self.phase == 0.0 && self.loop_gap == Duration::zero() &&
+            self.speed == 1.0 && !self.reversed && !self.discrete
+    }
+    /// The track as a `key_framed` or `fixed_value` Pon that translates back into an equivalent track.
+    /// Fails with AnimationError::Invalid if its curve can't be written as Pon.
+    pub fn to_pon(&self) -> Result<Pon, AnimationError> {
+        let property = Pon::DependencyReference(self.property.clone(), None);
+        let (type_name, mut fields) = match self.curve.to_pon() {
+            Some(Pon::TypedPon(box TypedPon { ref type_name, ref data })) if type_name == "fixed_value" => {
+                if self.has_fixed_value_defaults() {
+                    return Ok(typed_pon("fixed_value", object_pon(vec![("property", property), ("value", data.clone())])));
+                }
+                // fixed_value can't hold the other fields, a single key holds the same value
+                let keys = Pon::Array(vec![Pon::Array(vec![Pon::Number(0.0), data.clone()])]);
+                ("key_framed".to_string(), vec![("keys".to_string(), keys)].into_iter().collect())
+            },
+            Some(Pon::TypedPon(box TypedPon { type_name, data: Pon::Object(fields) })) => (type_name, fields),
+            _ => return Err(AnimationError::Invalid(format!("the curve of {:?} can't be written as pon", self.property)))
+        };
+        let seconds = |d: Duration| Pon::Number(d.num_milliseconds() as f32 / 1000.0);
+        fields.insert("property".to_string(), property);
+        fields.insert("offset".to_string(), seconds(self.offset));
+        fields.insert("loop".to_string(), self.loop_type.to_pon());
+        fields.insert("duration".to_string(), seconds(self.duration));
+        fields.insert("curve_time".to_string(), self.curve_time.to_pon());
+        fields.insert("phase".to_string(), Pon::Number(self.phase));
+        fields.insert("loop_gap".to_string(), seconds(self.loop_gap));
+        fields.insert("speed".to_string(), Pon::Number(self.speed));
+        fields.insert("reversed".to_string(), Pon::Boolean(self.reversed));
+        fields.insert("discrete".to_string(), Pon::Boolean(self.discrete));
+        Ok(typed_pon(&type_name, Pon::Object(fields)))
+    }
+}
+
+impl Track for CurveTrack {
+    fn value_at(&self, time: Duration) -> Vec<(NamedPropRef, Animatable)> {
+        let mut res = vec![];
+        self.value_at_into(time, &mut res);
+        res
+    }
+    fn value_at_into(&self, time: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) {
+        if let Some(value) = self.sample(time) {
+            out.push((self.property.clone(), value));
+        }
+    }
+    fn value_at_reusing(&self, time: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) {
+        self.write_reusing(self.sample(time), out);
+    }
+    fn value_at_many(&self, times: &[Duration]) -> Vec<Vec<(NamedPropRef, Animatable)>> {
+        let mut res = Vec::with_capacity(times.len());
+        for &time in times {
+            let mut frame = Vec::with_capacity(1);
+            self.value_at_into(time, &mut frame);
+            res.push(frame);
+        }
+        res
+    }
+    fn affected_properties(&self) -> Vec<NamedPropRef> {
+        vec![self.property.clone()]
+    }
+    fn is_constant_between(&self, from: Duration, to: Duration) -> bool {
+        // A track plays without interruption once started, until it ends
+        self.is_fixed_value() && self.local_time(from).is_some() && self.local_time(to).is_some()
+    }
+    fn seek_reusing(&self, from: Duration, to: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) -> Vec<Event> {
+        let crossed_end = self.loop_type == Loop::OnceThenHoldOneFrame && from < to &&
+            self.local_time(from).is_some() && self.local_time(to).is_none();
+        let value = if crossed_end { Some(self.end_value()) } else { self.sample(to) };
+        self.write_reusing(value, out);
+        if from < to { self.events_between(from, to) } else { vec![] }
+    }
+    fn duration(&self) -> Duration {
+        let cycles = match self.loop_type {
+            Loop::Forever | Loop::PingPong => return infinite_duration(),
+            Loop::Once | Loop::Hold | Loop::OnceThenHoldOneFrame => 1,
+            Loop::Count(n) => n as i64
         };
-        return vec![(self.property.clone(), self.curve.value(time))];
+        if cycles == 0 {
+            return self.offset;
+        }
+        let played_ms = cycles * self.duration.num_milliseconds() + (cycles - 1) * self.loop_gap.num_milliseconds();
+        // The phase skips the start of the first cycle, so playback ends that much earlier
+        let played_ms = (played_ms as f32 - self.phase * self.duration.num_milliseconds() as f32).max(0.0);
+        self.offset + Duration::milliseconds((played_ms / self.speed) as i64)
+    }
+    fn is_finished(&self, time: Duration) -> bool {
+        match self.loop_type {
+            // Keeps producing the end value
+            Loop::Hold => false,
+            _ => time > self.duration()
+        }
     }
 }
 
 
-impl Translatable<Loop> for Pon {
-    fn inner_translate(&self, context: &mut TranslateContext) -> Result<Loop, PonTranslateErr> {
+/// What to do with keys that have fewer components than others, since interpolating
+/// them would drop components
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ComponentPolicy {
+    /// Keys must all have the same number of components
+    Strict,
+    /// Shorter keys are padded with zeros
+    PadZero,
+    /// Shorter keys are padded with their last component
+    PadRepeat
+}
+
+impl Translatable<ComponentPolicy> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<ComponentPolicy, PonTranslateErr> {
         match try!(self.translate::<String>(context)).as_str() {
-            "forever" => Ok(Loop::Forever),
-            "once" => Ok(Loop::Once),
+            "strict" => Ok(ComponentPolicy::Strict),
+            "pad_zero" => Ok(ComponentPolicy::PadZero),
+            "pad_repeat" => Ok(ComponentPolicy::PadRepeat),
             _ => Err(PonTranslateErr::InvalidValue { value: format!("{:?}", self) })
         }
     }
 }
-impl Translatable<CurveTime> for Pon {
-    fn inner_translate(&self, context: &mut TranslateContext) -> Result<CurveTime, PonTranslateErr> {
-        match try!(self.translate::<String>(context)).as_str() {
-            "absolute" => Ok(CurveTime::Absolute),
-            "relative" => Ok(CurveTime::Relative),
-            _ => Err(PonTranslateErr::InvalidValue { value: format!("{:?}", self) })
+
+/// Makes every key have as many components as the longest one, following policy
+pub fn apply_component_policy(mut keys: Vec<Key<Animatable>>, policy: ComponentPolicy) -> Result<Vec<Key<Animatable>>, PonTranslateErr> {
+    if policy == ComponentPolicy::Strict {
+        if let Some(first) = keys.first() {
+            for (i, key) in keys.iter().enumerate() {
+                if key.1.value.len() != first.1.value.len() {
+                    return Err(PonTranslateErr::InvalidValue {
+                        value: format!("key {} has {} components, expected {}", i, key.1.value.len(), first.1.value.len())
+                    });
+                }
+            }
         }
+        return Ok(keys);
     }
+    let components = keys.iter().map(|key| key.1.value.len()).max().unwrap_or(0);
+    for key in keys.iter_mut() {
+        let value = &mut key.1.value;
+        let pad = match policy {
+            ComponentPolicy::PadRepeat => value.last().cloned().unwrap_or(0.0),
+            _ => 0.0
+        };
+        while value.len() < components {
+            value.push(pad);
+        }
+    }
+    Ok(keys)
 }
 
 impl Translatable<Key<Animatable>> for Pon {
     fn inner_translate(&self, context: &mut TranslateContext) -> Result<Key<Animatable>, PonTranslateErr> {
-        match self {
-            &Pon::Object(..) => {
-                let time: f32 = try!(self.field_as::<f32>("time", context));
-                let value = try!(self.field_as("value", context));
-                Ok(Key(time, value))
-            },
-            &Pon::Array(ref arr) => {
-                let time: f32 = try!(arr[0].translate::<f32>(context));
-                let value = try!(arr[1].translate(context));
-                Ok(Key(time, value))
+        translate_key(self, context)
+    }
+}
+
+/// Translates `curve_from_resource 'id'`, a curve registered in the document's resources
+/// as a `Shared<Box<Curve<Animatable>>>`
+fn translate_curve_resource(pon: &Pon, context: &mut TranslateContext) -> Result<SharedCurve<Animatable>, PonTranslateErr> {
+    pon.as_typed(|&TypedPon { ref type_name, ref data }| {
+        match type_name.as_str() {
+            "curve_from_resource" => {
+                let resource_id = try!(data.translate::<String>(context));
+                let curve = try!(find_resource::<Shared<Box<Curve<Animatable>>>>(context, type_name, &resource_id, "a curve"));
+                Ok(SharedCurve { curve: curve.clone() })
             },
-            &Pon::FloatArray(ref arr) => Ok(Key(arr[0], Animatable { value: vec![arr[1]] })),
-            _ => {
-                Err(PonTranslateErr::MismatchType { expected: "Object or Array".to_string(), found: format!("{:?}", self) })
-            }
+            s @ _ => Err(PonTranslateErr::UnrecognizedType(s.to_string()))
         }
+    })
+}
+
+fn to_curve_time(curve_time: &CurveTime, time: Duration, duration: Duration) -> f32 {
+    match *curve_time {
+        CurveTime::Absolute => time.num_milliseconds() as f32 / 1000.0,
+        CurveTime::Relative => (time.num_milliseconds() as f32 / duration.num_milliseconds() as f32).max(0.0).min(1.0),
+        CurveTime::Percent => (time.num_milliseconds() as f32 / duration.num_milliseconds() as f32).max(0.0).min(1.0) * 100.0,
+        CurveTime::Frames(fps) => time.num_milliseconds() as f32 * fps / 1000.0,
+        CurveTime::Beats(bpm) => time.num_milliseconds() as f32 * bpm / 60000.0
     }
 }
 
@@ -112,22 +519,71 @@ impl Translatable<CurveTrack> for Pon {
             match type_name.as_str() {
                 "key_framed" => {
                     let property: &NamedPropRef = try!(try!(data.field("property")).as_reference());
-                    let duration: f32 = try!(data.field_as_or("duration", 1.0, context));
                     let loop_type = try!(data.field_as_or("loop", Loop::Once, context));
                     let curve_time = try!(data.field_as_or("curve_time", CurveTime::Absolute, context));
-                    let curve: Box<Curve<Animatable>> = {
-                        let keys: PonAutoVec<Key<Animatable>> = try!(data.field_as("keys", context));
-                        Box::new(LinearKeyFrameCurve {
-                            keys: keys.0
-                        })
+                    let phase: f32 = try!(data.field_as_or("phase", 0.0, context));
+                    if !(phase >= 0.0 && phase < 1.0) {
+                        return Err(PonTranslateErr::InvalidValue { value: format!("phase must be from 0 up to 1, got {}", phase) });
+                    }
+                    let loop_gap = try!(duration_field_or(data, "loop_gap", Duration::zero(), context));
+                    if loop_gap < Duration::zero() {
+                        return Err(PonTranslateErr::InvalidValue { value: format!("loop_gap can't be negative, got {}ms", loop_gap.num_milliseconds()) });
+                    }
+                    let offset = try!(duration_field_or(data, "offset", Duration::zero(), context));
+                    let speed: f32 = try!(data.field_as_or("speed", 1.0, context));
+                    if !(speed > 0.0) {
+                        return Err(PonTranslateErr::InvalidValue { value: format!("speed must be positive, got {}", speed) });
+                    }
+                    let reversed = try!(data.field_as_or("reversed", false, context));
+                    let discrete = try!(data.field_as_or("discrete", false, context));
+                    let curve: Box<Curve<Animatable>> = if let Ok(curve) = data.field("curve") {
+                        Box::new(try!(translate_curve_resource(curve, context)))
+                    } else {
+                        let keys = try!(translate_keys::<InterpolatedKey>(try!(data.field("keys")), context));
+                        let keys = try!(sort_keys(keys.into_iter().map(|k| Key(k.key.0, (k.key.1, k.interpolation))).collect()));
+                        let key_interpolations = keys.iter().map(|k| (k.1).1).collect();
+                        let keys: Vec<Key<Animatable>> = keys.into_iter().map(|k| Key(k.0, (k.1).0)).collect();
+                        let policy = try!(data.field_as_or("components", ComponentPolicy::Strict, context));
+                        let keys = try!(apply_component_policy(keys, policy));
+                        let easing = try!(data.field_as_or("easing", Easing::Linear, context));
+                        let interpolation = try!(data.field_as_or("interp", Interpolation::Linear, context));
+                        let mut linear = LinearKeyFrameCurve::new(keys);
+                        if try!(data.field_as_or("cyclic", false, context)) {
+                            // The curve wraps around where the track loops, so it needs to know the duration
+                            if data.field("duration").is_err() && curve_time == CurveTime::Absolute {
+                                return Err(PonTranslateErr::InvalidValue { value: "cyclic key_framed needs a duration".to_string() });
+                            }
+                            let duration = try!(duration_field_or(data, "duration", Duration::seconds(1), context));
+                            linear.set_cyclic(Some(to_curve_time(&curve_time, duration, duration)));
+                        }
+                        InterpolatedKeyFrameCurve::new_boxed(linear, interpolation, key_interpolations, easing)
+                    };
+                    // Without a duration, absolute curves play until their last key
+                    let duration = match (data.field("duration"), &curve_time) {
+                        (Err(_), &CurveTime::Absolute) => {
+                            let duration = Duration::milliseconds((curve.domain().1 * 1000.0) as i64);
+                            if duration <= Duration::zero() {
+                                return Err(PonTranslateErr::InvalidValue { value: "key_framed needs a duration when its keys don't span any time".to_string() });
+                            }
+                            duration
+                        },
+                        _ => try!(duration_field_or(data, "duration", Duration::seconds(1), context))
                     };
+                    if duration <= Duration::zero() {
+                        return Err(PonTranslateErr::InvalidValue { value: format!("duration must be positive, got {}ms", duration.num_milliseconds()) });
+                    }
                     Ok(CurveTrack {
                         curve: curve,
-                        offset: Duration::zero(),
+                        offset: offset,
                         property: property.clone(),
                         loop_type: loop_type,
-                        duration: Duration::milliseconds((duration*1000.0) as i64),
-                        curve_time: curve_time
+                        duration: duration,
+                        curve_time: curve_time,
+                        phase: phase,
+                        loop_gap: loop_gap,
+                        speed: speed,
+                        reversed: reversed,
+                        discrete: discrete
                     })
                 },
                 "fixed_value" => {
@@ -146,14 +602,17 @@ impl Translatable<CurveTrack> for Pon {
 #[test]
 fn test_animation() {
     let kf = CurveTrack {
-        curve: Box::new(LinearKeyFrameCurve {
-            keys: vec![Key(0.0, Animatable::new_float(0.0)), Key(1.0, Animatable::new_float(1.0))]
-        }),
+        curve: Box::new(LinearKeyFrameCurve::new(vec![Key(0.0, Animatable::new_float(0.0)), Key(1.0, Animatable::new_float(1.0))])),
         offset: Duration::zero(),
         property: NamedPropRef::new(EntityPath::This, "x"),
         loop_type: Loop::Once,
         duration: Duration::seconds(1),
-        curve_time: CurveTime::Absolute
+        curve_time: CurveTime::Absolute,
+        phase: 0.0,
+        loop_gap: Duration::zero(),
+        speed: 1.0,
+        reversed: false,
+        discrete: false
     };
     assert_eq!(kf.value_at(Duration::milliseconds(100)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.1))]);
     assert_eq!(kf.value_at(Duration::milliseconds(600)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.6))]);
@@ -184,3 +643,686 @@ fn test_animation_from_pon_multivalue() {
         .unwrap().translate(&mut TranslateContext::empty()).unwrap();
     assert_eq!(kf.value_at(Duration::milliseconds(500)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new(vec![-1.0, 5.0]))]);
 }
+
+#[test]
+fn test_animation_ping_pong() {
+    let kf: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], loop: 'ping_pong' }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    for &x in [100, 250, 600, 900].iter() {
+        assert_eq!(kf.value_at(Duration::milliseconds(1000 + x)), kf.value_at(Duration::milliseconds(1000 - x)));
+    }
+    assert_eq!(kf.value_at(Duration::milliseconds(1500)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.5))]);
+    assert_eq!(kf.value_at(Duration::milliseconds(2250)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.25))]);
+}
+
+#[test]
+fn test_animation_loop_count() {
+    let kf: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], loop: ['count', 3] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(kf.loop_type, Loop::Count(3));
+    assert_eq!(kf.value_at(Duration::milliseconds(1250)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.25))]);
+    assert_eq!(kf.value_at(Duration::milliseconds(2500)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.5))]);
+    assert_eq!(kf.value_at(Duration::milliseconds(3000)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(1.0))]);
+    assert_eq!(kf.value_at(Duration::milliseconds(3100)), vec![]);
+    assert_eq!(kf.value_at(Duration::milliseconds(5500)), vec![]);
+}
+
+#[test]
+fn test_animation_hold() {
+    let kf: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [0.5, 0.25], [1.0, 2.0]], loop: 'hold' }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(kf.value_at(Duration::milliseconds(500)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.25))]);
+    for &t in [1000, 1001, 1500, 10000].iter() {
+        assert_eq!(kf.value_at(Duration::milliseconds(t)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(2.0))]);
+    }
+}
+
+#[test]
+fn test_animation_phase() {
+    let kf: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], loop: 'forever' }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let shifted: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], loop: 'forever', phase: 0.5 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    for &t in [0, 100, 400, 700, 1300].iter() {
+        assert_eq!(shifted.value_at(Duration::milliseconds(t)), kf.value_at(Duration::milliseconds(t + 500)));
+    }
+}
+
+#[test]
+fn test_animation_phase_once() {
+    let kf: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], phase: 0.5 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(kf.value_at(Duration::milliseconds(400)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.9))]);
+    assert_eq!(kf.value_at(Duration::milliseconds(600)), vec![]);
+    assert_eq!(kf.duration(), Duration::milliseconds(500));
+    assert!(!kf.is_finished(Duration::milliseconds(400)));
+    assert!(kf.is_finished(Duration::milliseconds(600)));
+}
+
+#[test]
+fn test_invalid_phase() {
+    for phase in &["-0.5", "1.0", "2.5"] {
+        let res: Result<CurveTrack, PonTranslateErr> = Pon::from_string(&format!(
+            "key_framed {{ property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], loop: 'forever', phase: {} }}", phase))
+            .unwrap().translate(&mut TranslateContext::empty());
+        match res {
+            Err(PonTranslateErr::InvalidValue { .. }) => {},
+            r @ _ => panic!("Expected InvalidValue for phase {}, got {:?}", phase, r)
+        }
+    }
+}
+
+#[test]
+#[should_panic]
+fn test_builder_invalid_phase() {
+    CurveTrackBuilder::new().phase(-0.5);
+}
+
+#[test]
+fn test_invalid_speed() {
+    for speed in &["0.0", "-1.0"] {
+        let res: Result<CurveTrack, PonTranslateErr> = Pon::from_string(&format!(
+            "key_framed {{ property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], speed: {} }}", speed))
+            .unwrap().translate(&mut TranslateContext::empty());
+        match res {
+            Err(PonTranslateErr::InvalidValue { .. }) => {},
+            r @ _ => panic!("Expected InvalidValue, got {:?}", r)
+        }
+    }
+}
+
+#[test]
+#[should_panic]
+fn test_builder_invalid_speed() {
+    CurveTrackBuilder::new().speed(0.0);
+}
+
+#[test]
+fn test_animation_long_running() {
+    let kf: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], loop: 'forever' }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    // Ten hours in, past where f32 can hold every millisecond
+    let hours = Duration::hours(10);
+    assert_eq!(kf.value_at(hours + Duration::milliseconds(1)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.001))]);
+    let mut faster = kf;
+    faster.speed = 2.0;
+    assert_eq!(faster.value_at(hours + Duration::milliseconds(1)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.002))]);
+}
+
+#[test]
+fn test_animation_loop_gap() {
+    let kf: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 2.0]], loop: 'forever', loop_gap: 0.5 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(kf.loop_gap, Duration::milliseconds(500));
+    for &t in [1000, 1200, 1499, 2999].iter() {
+        assert_eq!(kf.value_at(Duration::milliseconds(t)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(2.0))]);
+    }
+    assert_eq!(kf.value_at(Duration::milliseconds(1750)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.5))]);
+}
+
+#[test]
+fn test_negative_loop_gap() {
+    let res: Result<CurveTrack, PonTranslateErr> = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], duration: 1.0, loop: 'forever', loop_gap: -1.0 }")
+        .unwrap().translate(&mut TranslateContext::empty());
+    match res {
+        Err(PonTranslateErr::InvalidValue { .. }) => {},
+        r @ _ => panic!("Expected InvalidValue, got {:?}", r)
+    }
+}
+
+#[test]
+#[should_panic]
+fn test_builder_negative_loop_gap() {
+    CurveTrackBuilder::new().loop_gap(Duration::milliseconds(-1));
+}
+
+#[test]
+fn test_animation_speed() {
+    let kf: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], loop: 'forever' }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let fast: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], loop: 'forever', speed: 2.0 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(fast.value_at(Duration::milliseconds(50)), kf.value_at(Duration::milliseconds(100)));
+    assert_eq!(fast.value_at(Duration::milliseconds(300)), kf.value_at(Duration::milliseconds(600)));
+    assert_eq!(fast.value_at(Duration::milliseconds(700)), kf.value_at(Duration::milliseconds(400)));
+}
+
+#[test]
+fn test_animation_reversed() {
+    let kf: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], loop: 'forever', reversed: true }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(kf.value_at(Duration::milliseconds(0)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(1.0))]);
+    assert_eq!(kf.value_at(Duration::milliseconds(250)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.75))]);
+    assert_eq!(kf.value_at(Duration::milliseconds(1000)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.0))]);
+    assert_eq!(kf.value_at(Duration::milliseconds(1250)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.75))]);
+    assert_eq!(kf.value_at(Duration::milliseconds(2500)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.5))]);
+}
+
+#[test]
+fn test_animation_before_offset() {
+    let mut kf: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    kf.offset = Duration::seconds(1);
+    assert_eq!(kf.value_at(Duration::zero()), vec![]);
+    assert_eq!(kf.value_at(Duration::milliseconds(999)), vec![]);
+    assert_eq!(kf.value_at(Duration::milliseconds(1000)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.0))]);
+    assert_eq!(kf.value_at(Duration::milliseconds(1500)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.5))]);
+}
+
+#[test]
+fn test_animation_duration() {
+    let mut kf: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [2.0, 1.0]], duration: 2.0 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(kf.duration(), Duration::seconds(2));
+    kf.loop_type = Loop::Hold;
+    assert_eq!(kf.duration(), Duration::seconds(2));
+    kf.loop_type = Loop::Count(3);
+    kf.loop_gap = Duration::milliseconds(500);
+    assert_eq!(kf.duration(), Duration::seconds(7));
+    kf.offset = Duration::seconds(1);
+    kf.speed = 2.0;
+    assert_eq!(kf.duration(), Duration::milliseconds(4500));
+    kf.loop_type = Loop::Forever;
+    assert_eq!(kf.duration(), infinite_duration());
+    kf.loop_type = Loop::PingPong;
+    assert_eq!(kf.duration(), infinite_duration());
+}
+
+#[test]
+fn test_animation_is_finished() {
+    let mut kf: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], loop: 'once' }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    kf.offset = Duration::milliseconds(500);
+    assert!(!kf.is_finished(Duration::zero()));
+    assert!(!kf.is_finished(Duration::milliseconds(1500)));
+    assert!(kf.is_finished(Duration::milliseconds(1501)));
+    assert_eq!(kf.value_at(Duration::milliseconds(1501)), vec![]);
+    kf.loop_type = Loop::Count(2);
+    assert!(!kf.is_finished(Duration::milliseconds(2500)));
+    assert!(kf.is_finished(Duration::milliseconds(2501)));
+    kf.loop_type = Loop::Hold;
+    assert!(!kf.is_finished(Duration::seconds(100)));
+    kf.loop_type = Loop::Forever;
+    assert!(!kf.is_finished(Duration::seconds(100)));
+    kf.loop_type = Loop::PingPong;
+    assert!(!kf.is_finished(Duration::seconds(100)));
+}
+
+#[test]
+fn test_mismatched_component_counts() {
+    let res: Result<CurveTrack, PonTranslateErr> = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, [1.0, 1.0, 1.0]]] }")
+        .unwrap().translate(&mut TranslateContext::empty());
+    match res {
+        Err(err) => assert!(err.to_string().contains("key 1 has 3 components, expected 1")),
+        Ok(_) => panic!("expected mismatched keys to be rejected")
+    }
+}
+
+#[test]
+fn test_component_policies() {
+    let translate = |policy: &str| -> Result<CurveTrack, PonTranslateErr> {
+        Pon::from_string(&format!("key_framed {{ property: this.x, keys: [[0.0, 2.0], [1.0, [4.0, 6.0, 8.0]]], components: '{}' }}", policy))
+            .unwrap().translate(&mut TranslateContext::empty())
+    };
+    assert!(translate("strict").is_err());
+    let x = |v: Vec<f32>| vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new(v))];
+    let track = translate("pad_zero").unwrap();
+    assert_eq!(track.value_at(Duration::zero()), x(vec![2.0, 0.0, 0.0]));
+    assert_eq!(track.value_at(Duration::milliseconds(500)), x(vec![3.0, 3.0, 4.0]));
+    let track = translate("pad_repeat").unwrap();
+    assert_eq!(track.value_at(Duration::zero()), x(vec![2.0, 2.0, 2.0]));
+    assert_eq!(track.value_at(Duration::milliseconds(500)), x(vec![3.0, 4.0, 5.0]));
+}
+
+#[test]
+fn test_discrete() {
+    let track: CurveTrack = Pon::from_string(
+        "key_framed { property: this.frame, keys: [[0.0, 0.0], [0.5, 1.0], [1.0, 2.0]], discrete: true }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(track.value_at(Duration::milliseconds(250)), vec![(NamedPropRef::new(EntityPath::This, "frame"), Animatable::new_float(0.0))]);
+    assert_eq!(track.value_at(Duration::milliseconds(499)), vec![(NamedPropRef::new(EntityPath::This, "frame"), Animatable::new_float(0.0))]);
+    assert_eq!(track.value_at(Duration::milliseconds(500)), vec![(NamedPropRef::new(EntityPath::This, "frame"), Animatable::new_float(1.0))]);
+    assert_eq!(track.value_at(Duration::milliseconds(750)), vec![(NamedPropRef::new(EntityPath::This, "frame"), Animatable::new_float(1.0))]);
+}
+
+#[test]
+fn test_curve_time_frames() {
+    let track: CurveTrack = Pon::from_string(
+        "key_framed { property: this.frame, keys: [[0.0, 0.0], [24.0, 24.0]], curve_time: ['frames', 24] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(track.curve_time, CurveTime::Frames(24.0));
+    assert_eq!(track.value_at(Duration::milliseconds(500)), vec![(NamedPropRef::new(EntityPath::This, "frame"), Animatable::new_float(12.0))]);
+}
+
+#[test]
+fn test_curve_time_beats() {
+    let track: CurveTrack = Pon::from_string(
+        "key_framed { property: this.pulse, keys: [[0.0, 0.0], [2.0, 1.0]], curve_time: ['beats', 120] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(track.curve_time, CurveTime::Beats(120.0));
+    assert_eq!(track.value_at(Duration::milliseconds(500)), vec![(NamedPropRef::new(EntityPath::This, "pulse"), Animatable::new_float(0.5))]);
+}
+
+#[test]
+fn test_value_at_many() {
+    let track: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [0.5, 1.0], [1.0, 0.0]], loop: 'ping_pong' }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let times: Vec<Duration> = (0..50).map(|i| Duration::milliseconds(i * 70 - 300)).collect();
+    let single: Vec<Vec<(NamedPropRef, Animatable)>> = times.iter().map(|&time| track.value_at(time)).collect();
+    assert_eq!(track.value_at_many(&times), single);
+    let set: Box<Track> = Pon::from_string(
+        "track_set [ key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]] }, fixed_value { property: this.y, value: 0.5 } ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let single: Vec<Vec<(NamedPropRef, Animatable)>> = times.iter().map(|&time| set.value_at(time)).collect();
+    assert_eq!(set.value_at_many(&times), single);
+}
+
+#[test]
+fn test_offset_from_pon() {
+    let track: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 2.0], [1.0, 4.0]], offset: 0.5 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(track.offset, Duration::milliseconds(500));
+    assert_eq!(track.value_at(Duration::zero()), vec![]);
+    assert_eq!(track.value_at(Duration::milliseconds(500)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(2.0))]);
+    assert_eq!(track.value_at(Duration::milliseconds(1000)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(3.0))]);
+}
+
+#[test]
+fn test_loop_spellings() {
+    let translate_loop = |s: &str| -> Result<Loop, PonTranslateErr> {
+        Pon::from_string(s).unwrap().translate(&mut TranslateContext::empty())
+    };
+    assert_eq!(translate_loop("3").unwrap(), Loop::Count(3));
+    assert_eq!(translate_loop("['count', 3]").unwrap(), Loop::Count(3));
+    assert_eq!(translate_loop("'forever'").unwrap(), Loop::Forever);
+    assert_eq!(translate_loop("'once'").unwrap(), Loop::Once);
+    for invalid in &["'sometimes'", "['count', 1.5]", "['times', 3]", "-2", "0", "['count', 0]"] {
+        match translate_loop(invalid) {
+            Err(PonTranslateErr::InvalidValue { .. }) => {},
+            r @ _ => panic!("Expected InvalidValue for {}, got {:?}", invalid, r)
+        }
+    }
+}
+
+#[test]
+fn test_to_pon_round_trip() {
+    let sources = [
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [0.5, 2.0], [1.0, 1.0]], duration: 2.0, loop: ['count', 3], curve_time: 'relative', offset: 0.25 }",
+        "key_framed { property: this.position, keys: [[0.0, [0.0, 0.0, 0.0]], [24.0, [2.0, 4.0, 6.0]]], loop: 'ping_pong', curve_time: ['frames', 24], easing: 'ease_in_quad' }",
+        "key_framed { property: this.rotation, keys: [[0.0, 6.1], [1.0, 0.1]], interp: 'angle', reversed: true, speed: 2.0 }",
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [0.9, 1.0]], duration: 1.0, loop: 'forever', cyclic: true }",
+        "fixed_value { property: this.y, value: 0.5 }"
+    ];
+    for source in sources.iter() {
+        let track: CurveTrack = Pon::from_string(source).unwrap().translate(&mut TranslateContext::empty()).unwrap();
+        let pon = track.to_pon().unwrap();
+        let round_tripped: CurveTrack = pon.translate(&mut TranslateContext::empty()).unwrap();
+        assert_eq!(round_tripped.loop_type, track.loop_type);
+        assert_eq!(round_tripped.curve_time, track.curve_time);
+        for i in 0..60 {
+            let time = Duration::milliseconds(i * 110);
+            assert_eq!(round_tripped.value_at(time), track.value_at(time));
+        }
+    }
+}
+
+#[test]
+fn test_duration_strings() {
+    let track: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], duration: '500ms', offset: '2s', curve_time: 'relative' }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(track.duration, Duration::milliseconds(500));
+    assert_eq!(track.offset, Duration::seconds(2));
+    assert_eq!(track.value_at(Duration::milliseconds(2250)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.5))]);
+    let res: Result<CurveTrack, PonTranslateErr> = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], duration: '5 parsecs' }")
+        .unwrap().translate(&mut TranslateContext::empty());
+    match res {
+        Err(PonTranslateErr::InvalidValue { .. }) => {},
+        r @ _ => panic!("Expected InvalidValue, got {:?}", r)
+    }
+}
+
+#[test]
+fn test_key_error_index() {
+    let res: Result<CurveTrack, PonTranslateErr> = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0], { time: 2.0 }, [3.0, 0.0]] }")
+        .unwrap().translate(&mut TranslateContext::empty());
+    match res {
+        Err(err) => assert!(err.to_string().contains("keys[2]"), "{}", err.to_string()),
+        Ok(_) => panic!("expected the third key to be rejected")
+    }
+}
+
+#[test]
+fn test_non_positive_duration() {
+    for duration in &["0.0", "-1.0", "'0ms'"] {
+        let res: Result<CurveTrack, PonTranslateErr> = Pon::from_string(&format!(
+            "key_framed {{ property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], duration: {} }}", duration))
+            .unwrap().translate(&mut TranslateContext::empty());
+        match res {
+            Err(PonTranslateErr::InvalidValue { .. }) => {},
+            r @ _ => panic!("Expected InvalidValue for duration {}, got {:?}", duration, r)
+        }
+    }
+}
+
+#[test]
+fn test_empty_keys() {
+    for source in &["key_framed { property: this.x, keys: [] }", "key_framed { property: this.x }"] {
+        let res: Result<CurveTrack, PonTranslateErr> = Pon::from_string(source).unwrap().translate(&mut TranslateContext::empty());
+        assert!(res.is_err());
+    }
+    let res: Result<CurveTrack, PonTranslateErr> = Pon::from_string("key_framed { property: this.x, keys: [] }")
+        .unwrap().translate(&mut TranslateContext::empty());
+    assert!(res.unwrap_err().to_string().contains("keys can't be empty"));
+}
+
+#[cfg(feature = "serialize")]
+#[test]
+fn test_json_round_trip() {
+    let track: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], { time: 0.5, value: 2.0, interp: 'step' }, [1.0, 1.0]],
+            loop: ['count', 2], curve_time: ['beats', 120], easing: 'ease_in_quad', offset: 0.25 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let json = ::serde_json::to_string(&track.to_data().unwrap()).unwrap();
+    let data: CurveTrackData = ::serde_json::from_str(&json).unwrap();
+    assert_eq!(data, track.to_data().unwrap());
+    let round_tripped = CurveTrack::from_data(data);
+    for i in 0..40 {
+        let time = Duration::milliseconds(i * 90);
+        assert_eq!(round_tripped.value_at(time), track.value_at(time));
+    }
+}
+
+#[test]
+fn test_cyclic_to_data() {
+    let track: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [0.9, 1.0]], duration: 1.0, loop: 'forever', cyclic: true }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let round_tripped = CurveTrack::from_data(track.to_data().unwrap());
+    // Past the last key, where a curve that lost its period would hold the last key
+    for ms in &[950, 990, 1950] {
+        let time = Duration::milliseconds(*ms);
+        assert_eq!(round_tripped.value_at(time), track.value_at(time));
+    }
+}
+
+#[test]
+fn test_builder() {
+    let track = CurveTrackBuilder::new()
+        .property(NamedPropRef::new(EntityPath::This, "x"))
+        .duration(Duration::seconds(2))
+        .loop_type(Loop::Once)
+        .linear_keys(vec![Key(2.0, Animatable::new_float(4.0)), Key(0.0, Animatable::new_float(0.0))])
+        .build();
+    assert_eq!(track.value_at(Duration::milliseconds(500)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(1.0))]);
+    assert_eq!(track.value_at(Duration::milliseconds(2000)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(4.0))]);
+    assert_eq!(track.value_at(Duration::milliseconds(2500)), vec![]);
+    assert_eq!(track.offset, Duration::zero());
+    assert_eq!(track.curve_time, CurveTime::Absolute);
+}
+
+#[test]
+fn test_fixed_value_path() {
+    let fixed: CurveTrack = Pon::from_string("fixed_value { property: this.x, value: 0.5 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert!(fixed.is_fixed_value());
+    let mut key_framed: CurveTrack = Pon::from_string("key_framed { property: this.x, keys: [[0.0, 0.5], [1.0, 0.5]], duration: 1.0 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert!(!key_framed.is_fixed_value());
+    key_framed.loop_type = Loop::Forever;
+    for i in 0..30 {
+        let time = Duration::milliseconds(i * 100);
+        assert_eq!(fixed.value_at(time), key_framed.value_at(time));
+    }
+    assert!(fixed.is_constant_between(Duration::zero(), Duration::seconds(10)));
+    assert!(!key_framed.is_constant_between(Duration::zero(), Duration::seconds(10)));
+}
+
+/// Constant as far as tracks can tell, but its value is the time it was sampled at
+#[cfg(test)]
+#[derive(Debug)]
+struct SampleTimeCurve;
+
+#[cfg(test)]
+impl Curve<Animatable> for SampleTimeCurve {
+    fn value(&self, time: f32) -> Animatable {
+        Animatable::new_float(time)
+    }
+    fn domain(&self) -> (f32, f32) {
+        (0.0, 1.0)
+    }
+    fn is_constant(&self) -> bool {
+        true
+    }
+}
+
+#[test]
+fn test_fixed_value_skips_time_mapping() {
+    let mut track = CurveTrack::new_fixed_value(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.5));
+    track.curve = Box::new(SampleTimeCurve);
+    track.phase = 0.5;
+    track.speed = 2.0;
+    track.reversed = true;
+    track.loop_type = Loop::Forever;
+    let x = |value| vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(value))];
+    for i in 0..30 {
+        assert_eq!(track.value_at(Duration::milliseconds(i * 70)), x(0.0));
+    }
+    assert_eq!(track.value_at(infinite_duration()), x(0.0));
+    // Plays from 1s to 1.25s, the phase skips half the duration and the rest plays twice as fast
+    track.loop_type = Loop::Once;
+    track.offset = Duration::seconds(1);
+    track.duration = Duration::seconds(1);
+    assert_eq!(track.value_at(Duration::milliseconds(500)), vec![]);
+    assert_eq!(track.value_at(Duration::milliseconds(1200)), x(0.0));
+    assert_eq!(track.value_at(Duration::seconds(2)), vec![]);
+}
+
+#[test]
+fn test_to_pon_fixed_value_with_fields() {
+    let track = CurveTrackBuilder::new()
+        .property(NamedPropRef::new(EntityPath::This, "x"))
+        .fixed_value(Animatable::new_float(0.5))
+        .loop_type(Loop::Once)
+        .duration(Duration::seconds(2))
+        .offset(Duration::milliseconds(500))
+        .build();
+    let round_tripped: CurveTrack = track.to_pon().unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(round_tripped.loop_type, Loop::Once);
+    assert_eq!(round_tripped.duration(), track.duration());
+    for &ms in [0, 400, 500, 1500, 2500, 2600].iter() {
+        let time = Duration::milliseconds(ms);
+        assert_eq!(round_tripped.value_at(time), track.value_at(time));
+    }
+}
+
+#[test]
+fn test_to_pon_unwritable_curve() {
+    let mut track = CurveTrack::new_fixed_value(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.5));
+    track.curve = Box::new(DiscreetKeyFrameCurve { keys: vec![Key(0.0, Animatable::new_float(0.5))] });
+    match track.to_pon() {
+        Err(AnimationError::Invalid(_)) => {},
+        r @ _ => panic!("Expected Invalid, got {:?}", r)
+    }
+}
+
+#[test]
+fn test_value_range() {
+    let ramp: CurveTrack = Pon::from_string("key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], duration: 1.0 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(ramp.value_range(), vec![(0.0, 1.0)]);
+    let peaks: CurveTrack = Pon::from_string(
+        "key_framed { property: this.position, keys: [[0.0, [0.0, 1.0]], [0.333, [2.5, -1.0]], [1.0, [1.0, 0.0]]], duration: 1.0 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(peaks.value_range(), vec![(0.0, 2.5), (-1.0, 1.0)]);
+}
+
+#[test]
+fn test_curve_from_resource() {
+    let mut document = Document::new();
+    let curve: Box<Curve<Animatable>> = Box::new(LinearKeyFrameCurve::new(vec![Key(0.0, Animatable::new_float(0.0)), Key(1.0, Animatable::new_float(2.0))]));
+    document.resources.insert("bounce".to_string(), Box::new(Shared::new(curve)) as Box<Any>);
+    let set: TrackSet = Pon::from_string(
+        "track_set [
+            key_framed { property: this.x, curve: curve_from_resource 'bounce', duration: 1.0 },
+            key_framed { property: this.y, curve: curve_from_resource 'bounce', duration: 2.0, curve_time: 'relative' }
+        ]")
+        .unwrap().translate(&mut TranslateContext { document: Some(&document) }).unwrap();
+    assert_eq!(set.value_at(Duration::milliseconds(500)), vec![
+        (NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(1.0)),
+        (NamedPropRef::new(EntityPath::This, "y"), Animatable::new_float(0.5))
+    ]);
+    let track: CurveTrack = Pon::from_string("key_framed { property: this.x, curve: curve_from_resource 'bounce', duration: 2.0 }")
+        .unwrap().translate(&mut TranslateContext { document: Some(&document) }).unwrap();
+    let from_pon: CurveTrack = track.to_pon().unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let from_data = CurveTrack::from_data(track.to_data().unwrap());
+    for &ms in [0, 500, 1000, 1500].iter() {
+        let time = Duration::milliseconds(ms);
+        assert_eq!(from_pon.value_at(time), track.value_at(time));
+        assert_eq!(from_data.value_at(time), track.value_at(time));
+    }
+    let missing: Result<CurveTrack, PonTranslateErr> = Pon::from_string("key_framed { property: this.x, curve: curve_from_resource 'wobble' }")
+        .unwrap().translate(&mut TranslateContext { document: Some(&document) });
+    match missing {
+        Err(PonTranslateErr::InvalidValue { .. }) => {},
+        r @ _ => panic!("Expected InvalidValue, got {:?}", r)
+    }
+}
+
+#[test]
+fn test_relative_past_duration() {
+    let mut kf: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], duration: 2.0, curve_time: 'relative' }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let x = |v: f32| vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(v))];
+    let time = Duration::milliseconds(3000);
+    assert_eq!(kf.value_at(time), vec![]);
+    kf.loop_type = Loop::Hold;
+    assert_eq!(kf.value_at(time), x(1.0));
+    kf.loop_type = Loop::Forever;
+    assert_eq!(kf.value_at(time), x(0.5));
+    kf.loop_type = Loop::PingPong;
+    assert_eq!(kf.value_at(time), x(0.5));
+    assert_eq!(kf.value_at(Duration::milliseconds(3500)), x(0.25));
+    kf.loop_type = Loop::Count(2);
+    assert_eq!(kf.value_at(time), x(0.5));
+    kf.loop_type = Loop::Count(1);
+    assert_eq!(kf.value_at(time), vec![]);
+    // Times outside of the duration are clamped to the ends of the curve
+    assert_eq!(kf.curve_time_at(Duration::milliseconds(5000)), 1.0);
+    assert_eq!(kf.curve_time_at(Duration::milliseconds(-500)), 0.0);
+}
+
+#[test]
+fn test_cycle_at() {
+    let mut kf: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], duration: 1.0, loop: 'forever', offset: 1.0 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(kf.cycle_at(Duration::milliseconds(500)), None);
+    assert_eq!(kf.cycle_at(Duration::milliseconds(1500)), Some(0));
+    assert_eq!(kf.cycle_at(Duration::milliseconds(3500)), Some(2));
+    kf.loop_type = Loop::Once;
+    assert_eq!(kf.cycle_at(Duration::milliseconds(1500)), Some(0));
+    assert_eq!(kf.cycle_at(Duration::milliseconds(3500)), None);
+    kf.loop_type = Loop::Count(2);
+    assert_eq!(kf.cycle_at(Duration::milliseconds(2500)), Some(1));
+    assert_eq!(kf.cycle_at(Duration::milliseconds(3000)), Some(1));
+    assert_eq!(kf.cycle_at(Duration::milliseconds(3500)), None);
+}
+
+#[test]
+fn test_percent() {
+    let track: CurveTrack = Pon::from_string(
+        "key_framed { property: this.progress, keys: [[0.0, 0.0], [50.0, 0.8], [100.0, 1.0]], duration: 4.0, curve_time: 'percent' }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(track.curve_time, CurveTime::Percent);
+    assert_eq!(track.value_at(Duration::seconds(2)), vec![(NamedPropRef::new(EntityPath::This, "progress"), Animatable::new_float(0.8))]);
+    assert_eq!(track.value_at(Duration::seconds(4)), vec![(NamedPropRef::new(EntityPath::This, "progress"), Animatable::new_float(1.0))]);
+}
+
+#[test]
+fn test_duration_from_keys() {
+    let track: CurveTrack = Pon::from_string("key_framed { property: this.x, keys: [[0.0, 0.0], [2.0, 1.0]] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(track.duration(), Duration::seconds(2));
+    assert_eq!(track.value_at(Duration::seconds(1)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.5))]);
+    let res: Result<CurveTrack, PonTranslateErr> = Pon::from_string("key_framed { property: this.x, keys: [[0.0, 1.0]] }")
+        .unwrap().translate(&mut TranslateContext::empty());
+    match res {
+        Err(PonTranslateErr::InvalidValue { .. }) => {},
+        r @ _ => panic!("Expected InvalidValue, got {:?}", r)
+    }
+    let res: Result<CurveTrack, PonTranslateErr> = Pon::from_string("key_framed { property: this.x, keys: [[0.0, 1.0]], duration: 0.0 }")
+        .unwrap().translate(&mut TranslateContext::empty());
+    assert!(res.is_err());
+}
+
+#[test]
+fn test_once_then_hold_one_frame() {
+    let track: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], loop: 'once_then_hold_one_frame' }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let end = vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(1.0))];
+    assert_eq!(track.value_at(Duration::seconds(1)), end);
+    assert_eq!(track.value_at(Duration::milliseconds(1001)), vec![]);
+    // The frame that crosses the end gets the end value, the frames after it nothing
+    assert_eq!(track.seek(Duration::milliseconds(980), Duration::milliseconds(1010)).values, end);
+    assert_eq!(track.seek(Duration::milliseconds(1010), Duration::milliseconds(1040)).values, vec![]);
+    assert_eq!(track.duration(), Duration::seconds(1));
+}
+
+#[test]
+fn test_cyclic() {
+    let track: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [0.9, 1.0]], duration: 1.0, loop: 'forever', cyclic: true }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let x = |ms| track.value_at(Duration::milliseconds(ms))[0].1.value[0];
+    assert_eq!(x(900), 1.0);
+    // Between the last key and the loop point the value returns towards the first key
+    assert!((x(950) - 0.5).abs() < 0.0001);
+    assert!(x(990) < 0.2);
+    assert_eq!(x(1000), 0.0);
+    assert!((x(1950) - 0.5).abs() < 0.0001);
+    let res: Result<CurveTrack, PonTranslateErr> = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [0.9, 1.0]], loop: 'forever', cyclic: true }")
+        .unwrap().translate(&mut TranslateContext::empty());
+    assert!(res.is_err());
+}
+
+#[test]
+fn test_value_at_reusing() {
+    let track: CurveTrack = Pon::from_string("key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let mut out = vec![];
+    track.value_at_reusing(Duration::milliseconds(100), &mut out);
+    let name = out[0].0.property_key.as_ptr();
+    for i in 2..10 {
+        let time = Duration::milliseconds(i * 100);
+        track.value_at_reusing(time, &mut out);
+        assert_eq!(out, track.value_at(time));
+        // The property ref from the first sample is kept, its name isn't allocated again
+        assert_eq!(out[0].0.property_key.as_ptr(), name);
+    }
+    track.value_at_reusing(Duration::seconds(2), &mut out);
+    assert_eq!(out, vec![]);
+}
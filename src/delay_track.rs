@@ -0,0 +1,105 @@
+
+use std::cmp;
+use time::*;
+use track::*;
+use pyramid::pon::*;
+use animatable::*;
+use event_track::*;
+
+/// Starts a track later.
+#[derive(Debug)]
+pub struct DelayTrack {
+    pub track: Box<Track>,
+    pub delay: Duration
+}
+
+impl Track for DelayTrack {
+    fn value_at(&self, time: Duration) -> Vec<(NamedPropRef, Animatable)> {
+        if time < self.delay {
+            return vec![];
+        }
+        self.track.value_at(time - self.delay)
+    }
+    fn value_at_into(&self, time: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) {
+        if time >= self.delay {
+            self.track.value_at_into(time - self.delay, out);
+        }
+    }
+    fn value_at_reusing(&self, time: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) {
+        if time < self.delay {
+            out.clear();
+        } else {
+            self.track.value_at_reusing(time - self.delay, out);
+        }
+    }
+    fn seek_reusing(&self, from: Duration, to: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) -> Vec<Event> {
+        if to < self.delay {
+            out.clear();
+            return vec![];
+        }
+        self.track.seek_reusing(cmp::max(from, self.delay) - self.delay, to - self.delay, out)
+    }
+    fn affected_properties(&self) -> Vec<NamedPropRef> {
+        self.track.affected_properties()
+    }
+    fn duration(&self) -> Duration {
+        let duration = self.track.duration();
+        if duration == infinite_duration() {
+            return duration;
+        }
+        self.delay + duration
+    }
+    fn is_finished(&self, time: Duration) -> bool {
+        time >= self.delay && self.track.is_finished(time - self.delay)
+    }
+    fn events_between(&self, from: Duration, to: Duration) -> Vec<Event> {
+        let from = cmp::max(from, self.delay);
+        if from < to {
+            self.track.events_between(from - self.delay, to - self.delay)
+        } else {
+            vec![]
+        }
+    }
+}
+
+impl Translatable<DelayTrack> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<DelayTrack, PonTranslateErr> {
+        self.as_typed(|&TypedPon { ref type_name, ref data }| {
+            match type_name.as_str() {
+                "delay" => {
+                    let by: f32 = try!(data.field_as::<f32>("by", context));
+                    Ok(DelayTrack {
+                        track: try!(data.field_as::<Box<Track>>("track", context)),
+                        delay: Duration::milliseconds((by*1000.0) as i64)
+                    })
+                },
+                s @ _ => Err(PonTranslateErr::UnrecognizedType(s.to_string()))
+            }
+        })
+    }
+}
+
+#[test]
+fn test_delay() {
+    let delayed: DelayTrack = Pon::from_string(
+        "delay { track: key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]] }, by: 0.5 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(delayed.value_at(Duration::zero()), vec![]);
+    assert_eq!(delayed.value_at(Duration::milliseconds(499)), vec![]);
+    for &t in [500, 750, 1200, 1500].iter() {
+        assert_eq!(delayed.value_at(Duration::milliseconds(t)), delayed.track.value_at(Duration::milliseconds(t - 500)));
+    }
+    assert_eq!(delayed.value_at(Duration::milliseconds(1000)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.5))]);
+    assert_eq!(delayed.duration(), Duration::milliseconds(1500));
+}
+
+#[test]
+fn test_delay_seek_holds_end_frame() {
+    let delayed: DelayTrack = Pon::from_string(
+        "delay { track: key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], loop: 'once_then_hold_one_frame' }, by: 0.5 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let x = |value| vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(value))];
+    assert_eq!(delayed.seek(Duration::milliseconds(400), Duration::milliseconds(1000)).values, x(0.5));
+    assert_eq!(delayed.seek(Duration::milliseconds(1400), Duration::milliseconds(1600)).values, x(1.0));
+    assert_eq!(delayed.seek(Duration::milliseconds(1600), Duration::milliseconds(1700)).values, vec![]);
+}
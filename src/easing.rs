@@ -0,0 +1,195 @@
+#[cfg(test)]
+use curve_track::*;
+#[cfg(test)]
+use track::*;
+#[cfg(test)]
+use time::*;
+
+use std::f32::consts::PI;
+use std::fmt::Debug;
+use pyramid::pon::*;
+use curve::*;
+use animatable::*;
+use interpolation::*;
+use track::ThreadSafety;
+
+/// Built in easing functions, mapping a segment fraction (0 to 1) to an eased fraction.
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Easing {
+    Linear,
+    EaseInQuad,
+    EaseOutQuad,
+    EaseInOutQuad,
+    EaseInCubic,
+    EaseOutCubic,
+    EaseInOutCubic,
+    EaseInQuart,
+    EaseOutQuart,
+    EaseInOutQuart,
+    EaseInSine,
+    EaseOutSine,
+    EaseInOutSine
+}
+
+const EASINGS: [(&'static str, Easing); 13] = [
+    ("linear", Easing::Linear),
+    ("ease_in_quad", Easing::EaseInQuad),
+    ("ease_out_quad", Easing::EaseOutQuad),
+    ("ease_in_out_quad", Easing::EaseInOutQuad),
+    ("ease_in_cubic", Easing::EaseInCubic),
+    ("ease_out_cubic", Easing::EaseOutCubic),
+    ("ease_in_out_cubic", Easing::EaseInOutCubic),
+    ("ease_in_quart", Easing::EaseInQuart),
+    ("ease_out_quart", Easing::EaseOutQuart),
+    ("ease_in_out_quart", Easing::EaseInOutQuart),
+    ("ease_in_sine", Easing::EaseInSine),
+    ("ease_out_sine", Easing::EaseOutSine),
+    ("ease_in_out_sine", Easing::EaseInOutSine)
+];
+
+fn ease_in(p: f32, n: i32) -> f32 {
+    p.powi(n)
+}
+fn ease_out(p: f32, n: i32) -> f32 {
+    1.0 - (1.0 - p).powi(n)
+}
+fn ease_in_out(p: f32, n: i32) -> f32 {
+    if p < 0.5 {
+        2.0f32.powi(n - 1) * p.powi(n)
+    } else {
+        1.0 - (2.0 - 2.0 * p).powi(n) / 2.0
+    }
+}
+
+impl Easing {
+    pub fn from_name(name: &str) -> Option<Easing> {
+        EASINGS.iter().find(|&&(n, _)| n == name).map(|&(_, e)| e)
+    }
+    pub fn name(&self) -> &'static str {
+        EASINGS.iter().find(|&&(_, e)| e == *self).map(|&(n, _)| n).unwrap()
+    }
+    pub fn apply(&self, p: f32) -> f32 {
+        match *self {
+            Easing::Linear => p,
+            Easing::EaseInQuad => ease_in(p, 2),
+            Easing::EaseOutQuad => ease_out(p, 2),
+            Easing::EaseInOutQuad => ease_in_out(p, 2),
+            Easing::EaseInCubic => ease_in(p, 3),
+            Easing::EaseOutCubic => ease_out(p, 3),
+            Easing::EaseInOutCubic => ease_in_out(p, 3),
+            Easing::EaseInQuart => ease_in(p, 4),
+            Easing::EaseOutQuart => ease_out(p, 4),
+            Easing::EaseInOutQuart => ease_in_out(p, 4),
+            Easing::EaseInSine => 1.0 - (p * PI / 2.0).cos(),
+            Easing::EaseOutSine => (p * PI / 2.0).sin(),
+            Easing::EaseInOutSine => -((PI * p).cos() - 1.0) / 2.0
+        }
+    }
+}
+
+/// A key frame curve where every segment is eased with the same easing function.
+#[derive(PartialEq, Debug)]
+pub struct EasedKeyFrameCurve<T: Clone> {
+    pub curve: LinearKeyFrameCurve<T>,
+    pub easing: Easing
+}
+
+impl<T: Interpolateable + Debug + Clone + ThreadSafety + 'static> EasedKeyFrameCurve<T> {
+    /// Boxes the curve, skipping the easing step entirely for linear easing
+    pub fn new_boxed(curve: LinearKeyFrameCurve<T>, easing: Easing) -> Box<Curve<T>> {
+        match easing {
+            Easing::Linear => Box::new(curve),
+            _ => Box::new(EasedKeyFrameCurve { curve: curve, easing: easing })
+        }
+    }
+}
+
+impl<T: Interpolateable + Debug + Clone + ThreadSafety> Curve<T> for EasedKeyFrameCurve<T> {
+    fn value(&self, time: f32) -> T {
+        let (a, b, p) = self.curve.segment(time);
+        interpolate_finite(a, b, self.easing.apply(p))
+    }
+    fn stepped_value(&self, time: f32) -> T {
+        self.curve.stepped_value(time)
+    }
+    fn key_times(&self) -> Vec<f32> {
+        self.curve.key_times()
+    }
+    fn to_pon(&self) -> Option<Pon> where T: ToPon {
+        Some(self.curve.key_framed_pon(vec![
+            ("keys", self.curve.keys_to_pon()),
+            ("easing", Pon::String(self.easing.name().to_string()))
+        ]))
+    }
+    fn to_data(&self) -> Option<CurveData<T>> where T: Clone {
+        Some(CurveData::KeyFramed {
+            keys: self.curve.keys().to_vec(),
+            easing: self.easing,
+            interpolation: Interpolation::Linear,
+            key_interpolations: vec![],
+            cycle_period: self.curve.cycle_period()
+        })
+    }
+    fn domain(&self) -> (f32, f32) {
+        self.curve.domain()
+    }
+}
+
+impl Translatable<Easing> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<Easing, PonTranslateErr> {
+        let name = try!(self.translate::<String>(context));
+        match Easing::from_name(name.as_str()) {
+            Some(easing) => Ok(easing),
+            None => Err(PonTranslateErr::InvalidValue { value: name })
+        }
+    }
+}
+
+#[test]
+fn test_easing_linear_matches_linear_curve() {
+    let keys = vec![Key(0.0, 0.0), Key(1.0, 2.0), Key(3.0, -1.0)];
+    let linear = LinearKeyFrameCurve::new(keys.clone());
+    let eased = EasedKeyFrameCurve { curve: LinearKeyFrameCurve::new(keys), easing: Easing::Linear };
+    for i in 0..40 {
+        let t = -0.5 + i as f32 * 0.1;
+        assert_eq!(eased.value(t), linear.value(t));
+    }
+}
+
+#[test]
+fn test_easing_linear_from_pon() {
+    let eased: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], easing: 'linear' }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let linear: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(eased.value_at(Duration::milliseconds(300)), linear.value_at(Duration::milliseconds(300)));
+}
+
+#[test]
+fn test_easing_ends() {
+    for &(_, easing) in EASINGS.iter() {
+        assert!(easing.apply(0.0).abs() < 0.0001);
+        assert!((easing.apply(1.0) - 1.0).abs() < 0.0001);
+    }
+    assert_eq!(Easing::EaseInCubic.apply(0.5), 0.125);
+}
+
+#[test]
+fn test_easing_unknown_name() {
+    let res: Result<CurveTrack, PonTranslateErr> = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], easing: 'bouncy' }")
+        .unwrap().translate(&mut TranslateContext::empty());
+    match res {
+        Err(PonTranslateErr::InvalidValue { .. }) => {},
+        r @ _ => panic!("Expected InvalidValue, got {:?}", r)
+    }
+}
+
+#[test]
+fn test_eased_domain() {
+    let eased = EasedKeyFrameCurve { curve: LinearKeyFrameCurve::new(vec![Key(0.25, 0.0), Key(4.0, 1.0)]), easing: Easing::EaseInQuad };
+    assert_eq!(eased.domain(), (0.25, 4.0));
+}
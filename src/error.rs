@@ -0,0 +1,94 @@
+
+use std::error::Error;
+use std::fmt;
+use pyramid::pon::*;
+#[cfg(feature = "gltf")]
+use gltf_import::*;
+#[cfg(test)]
+use track::*;
+
+/// A PonTranslateErr as a std::error::Error, so it can be the cause of an AnimationError
+#[derive(Debug)]
+pub struct TranslateError(pub PonTranslateErr);
+
+impl fmt::Display for TranslateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.to_string())
+    }
+}
+
+impl Error for TranslateError {
+    fn description(&self) -> &str {
+        "failed to translate pon"
+    }
+}
+
+/// The errors returned by this crate's public functions
+#[derive(Debug)]
+pub enum AnimationError {
+    Translate(TranslateError),
+    #[cfg(feature = "gltf")]
+    Import(ImportError),
+    /// Input that was read fine, but doesn't make a valid animation
+    Invalid(String)
+}
+
+impl fmt::Display for AnimationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AnimationError::Translate(ref err) => write!(f, "translate error: {}", err),
+            #[cfg(feature = "gltf")]
+            AnimationError::Import(ref err) => write!(f, "import error: {}", err),
+            AnimationError::Invalid(ref err) => write!(f, "invalid animation: {}", err)
+        }
+    }
+}
+
+impl Error for AnimationError {
+    fn description(&self) -> &str {
+        match *self {
+            AnimationError::Translate(_) => "failed to translate animation",
+            #[cfg(feature = "gltf")]
+            AnimationError::Import(_) => "failed to import animation",
+            AnimationError::Invalid(_) => "invalid animation"
+        }
+    }
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            AnimationError::Translate(ref err) => Some(err),
+            #[cfg(feature = "gltf")]
+            AnimationError::Import(ref err) => Some(err),
+            AnimationError::Invalid(_) => None
+        }
+    }
+}
+
+impl From<PonTranslateErr> for AnimationError {
+    fn from(err: PonTranslateErr) -> AnimationError {
+        AnimationError::Translate(TranslateError(err))
+    }
+}
+
+#[cfg(feature = "gltf")]
+impl From<ImportError> for AnimationError {
+    fn from(err: ImportError) -> AnimationError {
+        AnimationError::Import(err)
+    }
+}
+
+#[cfg(test)]
+fn translate_track(source: &str) -> Result<Box<Track>, AnimationError> {
+    let pon = try!(Pon::from_string(source).map_err(|_| AnimationError::Invalid(source.to_string())));
+    Ok(try!(pon.translate::<Box<Track>>(&mut TranslateContext::empty())))
+}
+
+#[test]
+fn test_boxed_error_cause() {
+    assert!(translate_track("fixed_value { property: this.x, value: 0.5 }").is_ok());
+    let err: Box<Error> = Box::new(translate_track("wobble { property: this.x }").unwrap_err());
+    let cause = err.cause().unwrap();
+    assert_eq!(cause.to_string(), PonTranslateErr::UnrecognizedType("wobble".to_string()).to_string());
+    assert_eq!(err.to_string(), format!("translate error: {}", cause));
+    let err: Box<Error> = Box::new(AnimationError::Invalid("no keys".to_string()));
+    assert!(err.cause().is_none());
+}
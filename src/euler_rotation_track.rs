@@ -0,0 +1,113 @@
+
+use time::*;
+use track::*;
+use pyramid::pon::*;
+use animatable::*;
+use curve::*;
+use curve_track::*;
+use event_track::*;
+
+/// Animates a quaternion property from euler angle keys. The angles are interpolated as they are,
+/// and only turned into a quaternion when sampled, so authors can key intuitive angles while the
+/// property always gets a normalized `[x, y, z, w]` quaternion.
+#[derive(Debug)]
+pub struct EulerRotationTrack {
+    /// Plays `[x, y, z]` angles in degrees
+    pub angles: CurveTrack
+}
+
+/// Multiplies two `[x, y, z, w]` quaternions, the result rotates by b and then by a
+fn quaternion_product(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+    [
+        a[3] * b[0] + a[0] * b[3] + a[1] * b[2] - a[2] * b[1],
+        a[3] * b[1] - a[0] * b[2] + a[1] * b[3] + a[2] * b[0],
+        a[3] * b[2] + a[0] * b[1] - a[1] * b[0] + a[2] * b[3],
+        a[3] * b[3] - a[0] * b[0] - a[1] * b[1] - a[2] * b[2]
+    ]
+}
+
+/// The normalized `[x, y, z, w]` quaternion rotating around x, then y, then z by the angles in degrees.
+/// Missing angles are 0.
+pub fn euler_to_quaternion(angles: &Animatable) -> Animatable {
+    let half = |i: usize| angles.value.get(i).map(|a| a.to_radians() / 2.0).unwrap_or(0.0);
+    let (x, y, z) = (half(0), half(1), half(2));
+    let q = quaternion_product([0.0, 0.0, z.sin(), z.cos()],
+        quaternion_product([0.0, y.sin(), 0.0, y.cos()], [x.sin(), 0.0, 0.0, x.cos()]));
+    let len = q.iter().fold(0.0, |sum, x| sum + x * x).sqrt();
+    Animatable::new(q.iter().map(|x| x / len).collect())
+}
+
+fn to_quaternions(values: &mut [(NamedPropRef, Animatable)]) {
+    for update in values.iter_mut() {
+        update.1 = euler_to_quaternion(&update.1);
+    }
+}
+
+impl Track for EulerRotationTrack {
+    fn value_at(&self, time: Duration) -> Vec<(NamedPropRef, Animatable)> {
+        let mut values = self.angles.value_at(time);
+        to_quaternions(&mut values);
+        values
+    }
+    fn value_at_reusing(&self, time: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) {
+        self.angles.value_at_reusing(time, out);
+        to_quaternions(out);
+    }
+    fn seek_reusing(&self, from: Duration, to: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) -> Vec<Event> {
+        let events = self.angles.seek_reusing(from, to, out);
+        to_quaternions(out);
+        events
+    }
+    fn affected_properties(&self) -> Vec<NamedPropRef> {
+        self.angles.affected_properties()
+    }
+    fn duration(&self) -> Duration {
+        self.angles.duration()
+    }
+    fn is_finished(&self, time: Duration) -> bool {
+        self.angles.is_finished(time)
+    }
+    fn events_between(&self, from: Duration, to: Duration) -> Vec<Event> {
+        self.angles.events_between(from, to)
+    }
+    fn is_constant_between(&self, from: Duration, to: Duration) -> bool {
+        self.angles.is_constant_between(from, to)
+    }
+}
+
+impl Translatable<EulerRotationTrack> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<EulerRotationTrack, PonTranslateErr> {
+        self.as_typed(|&TypedPon { ref type_name, ref data }| {
+            match type_name.as_str() {
+                // Takes the same fields as key_framed
+                "euler_rotation" => Ok(EulerRotationTrack {
+                    angles: try!(typed_pon("key_framed", data.clone()).translate::<CurveTrack>(context))
+                }),
+                s @ _ => Err(PonTranslateErr::UnrecognizedType(s.to_string()))
+            }
+        })
+    }
+}
+
+#[test]
+fn test_euler_yaw() {
+    let track: EulerRotationTrack = Pon::from_string(
+        "euler_rotation { property: this.rotation, keys: [[0.0, [0.0, 0.0, 0.0]], [1.0, [0.0, 90.0, 0.0]]] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let (prop, mid) = track.value_at(Duration::milliseconds(500)).pop().unwrap();
+    assert_eq!(prop, NamedPropRef::new(EntityPath::This, "rotation"));
+    // 45 degrees around y
+    let eighth = 22.5f32.to_radians();
+    for (x, y) in mid.value.iter().zip([0.0, eighth.sin(), 0.0, eighth.cos()].iter()) {
+        assert!((x - y).abs() < 0.0001, "{:?}", mid);
+    }
+}
+
+#[test]
+fn test_euler_order() {
+    // Rotating x by 90 degrees and then z by 90 degrees
+    let q = euler_to_quaternion(&Animatable::new(vec![90.0, 0.0, 90.0]));
+    for (x, y) in q.value.iter().zip([0.5, 0.5, 0.5, 0.5].iter()) {
+        assert!((x - y).abs() < 0.0001, "{:?}", q);
+    }
+}
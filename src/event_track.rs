@@ -0,0 +1,114 @@
+
+use time::*;
+use track::*;
+use pyramid::pon::*;
+use animatable::*;
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Event {
+    pub time: Duration,
+    pub name: String
+}
+
+/// A track that produces no property values, only named events at specific times.
+#[derive(Debug)]
+pub struct EventTrack {
+    pub events: Vec<Event>
+}
+
+impl Track for EventTrack {
+    fn value_at(&self, _: Duration) -> Vec<(NamedPropRef, Animatable)> {
+        vec![]
+    }
+    fn affected_properties(&self) -> Vec<NamedPropRef> {
+        vec![]
+    }
+    fn duration(&self) -> Duration {
+        self.events.iter().map(|e| e.time).max().unwrap_or(Duration::zero())
+    }
+    fn events_between(&self, from: Duration, to: Duration) -> Vec<Event> {
+        let end = self.duration();
+        self.events.iter().filter(|e| {
+            if e.time == end && end > Duration::zero() {
+                // Playback that stops at the end never goes past it, so events there fire once to reaches them
+                from < end && to >= end
+            } else {
+                e.time >= from && e.time < to
+            }
+        }).cloned().collect()
+    }
+}
+
+impl Translatable<Event> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<Event, PonTranslateErr> {
+        let time: f32 = try!(self.field_as::<f32>("time", context));
+        let name: String = try!(self.field_as::<String>("name", context));
+        Ok(Event {
+            time: Duration::milliseconds((time*1000.0) as i64),
+            name: name
+        })
+    }
+}
+
+impl Translatable<EventTrack> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<EventTrack, PonTranslateErr> {
+        self.as_typed(|&TypedPon { ref type_name, ref data }| {
+            match type_name.as_str() {
+                "events" => {
+                    let events: PonAutoVec<Event> = try!(data.field_as("events", context));
+                    Ok(EventTrack {
+                        events: events.0
+                    })
+                },
+                s @ _ => Err(PonTranslateErr::UnrecognizedType(s.to_string()))
+            }
+        })
+    }
+}
+
+#[test]
+fn test_events_between() {
+    let track: Box<Track> = Pon::from_string(
+        "events { events: [{ time: 0.5, name: 'footstep' }, { time: 1.0, name: 'spawn' }] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let fired: Vec<Event> = vec![
+        track.events_between(Duration::milliseconds(300), Duration::milliseconds(400)),
+        track.events_between(Duration::milliseconds(400), Duration::milliseconds(600)),
+        track.events_between(Duration::milliseconds(600), Duration::milliseconds(800))
+    ].into_iter().flat_map(|e| e.into_iter()).collect();
+    assert_eq!(fired, vec![Event { time: Duration::milliseconds(500), name: "footstep".to_string() }]);
+    assert_eq!(track.value_at(Duration::milliseconds(500)), vec![]);
+}
+
+#[test]
+fn test_event_track_duration() {
+    let track: EventTrack = Pon::from_string(
+        "events { events: [{ time: 1.5, name: 'spawn' }, { time: 0.5, name: 'footstep' }] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(track.duration(), Duration::milliseconds(1500));
+    assert_eq!(EventTrack { events: vec![] }.duration(), Duration::zero());
+}
+
+#[test]
+fn test_seek_backwards() {
+    let track: Box<Track> = Pon::from_string("events { events: [{ time: 0.5, name: 'footstep' }] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(track.seek(Duration::milliseconds(400), Duration::milliseconds(600)).events.len(), 1);
+    assert_eq!(track.seek(Duration::milliseconds(600), Duration::milliseconds(400)).events, vec![]);
+    assert_eq!(track.seek(Duration::milliseconds(400), Duration::milliseconds(450)).events, vec![]);
+    assert_eq!(track.seek(Duration::milliseconds(450), Duration::milliseconds(550)).events.len(), 1);
+}
+
+#[test]
+fn test_event_at_end() {
+    let track: EventTrack = Pon::from_string(
+        "events { events: [{ time: 0.5, name: 'footstep' }, { time: 1.0, name: 'spawn' }] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let spawn = vec![Event { time: Duration::seconds(1), name: "spawn".to_string() }];
+    // Playback clamped at the end
+    assert_eq!(track.events_between(Duration::milliseconds(600), Duration::seconds(1)), spawn);
+    assert_eq!(track.events_between(Duration::seconds(1), Duration::seconds(1)), vec![]);
+    assert_eq!(track.events_between(Duration::seconds(1), Duration::milliseconds(1200)), vec![]);
+    // Playback going past the end
+    assert_eq!(track.events_between(Duration::milliseconds(900), Duration::milliseconds(1100)), spawn);
+}
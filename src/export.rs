@@ -0,0 +1,75 @@
+#[cfg(test)]
+use pyramid::pon::*;
+
+use time::*;
+use track::*;
+use animatable::*;
+
+fn column_name(prop: &NamedPropRef) -> String {
+    match prop.entity_path {
+        EntityPath::This => format!("this.{}", prop.property_key),
+        ref path => format!("{:?}.{}", path, prop.property_key)
+    }
+}
+
+/// Samples track every step from start to end (inclusive) and writes the values as csv, with the
+/// time in seconds in the first column and a column per property component after it.
+/// Properties that aren't written at a time are left blank in that row.
+pub fn export_csv(track: &Track, start: Duration, end: Duration, step: Duration) -> String {
+    let mut rows: Vec<(Duration, Vec<(NamedPropRef, Animatable)>)> = vec![];
+    let mut time = start;
+    while time <= end {
+        rows.push((time, track.value_at(time)));
+        if step <= Duration::zero() {
+            break;
+        }
+        time = time + step;
+    }
+    // Columns in the order the properties first appear, wide enough for their largest value
+    let mut columns: Vec<(NamedPropRef, usize)> = vec![];
+    for &(_, ref values) in &rows {
+        for &(ref prop, ref value) in values {
+            match columns.iter().position(|&(ref p, _)| p == prop) {
+                Some(i) => if value.value.len() > columns[i].1 { columns[i].1 = value.value.len() },
+                None => columns.push((prop.clone(), value.value.len()))
+            }
+        }
+    }
+    let mut header = vec!["time".to_string()];
+    for &(ref prop, components) in &columns {
+        if components == 1 {
+            header.push(column_name(prop));
+        } else {
+            for c in 0..components {
+                header.push(format!("{}.{}", column_name(prop), c));
+            }
+        }
+    }
+    let mut csv = header.join(",") + "\n";
+    for &(time, ref values) in &rows {
+        let mut row = vec![format!("{}", time.num_milliseconds() as f32 / 1000.0)];
+        for &(ref prop, components) in &columns {
+            let value = values.iter().find(|&&(ref p, _)| p == prop).map(|&(_, ref v)| v);
+            for c in 0..components {
+                row.push(match value.and_then(|v| v.value.get(c)) {
+                    Some(x) => format!("{}", x),
+                    None => "".to_string()
+                });
+            }
+        }
+        csv = csv + &row.join(",") + "\n";
+    }
+    csv
+}
+
+#[test]
+fn test_export_csv() {
+    let track: Box<Track> = Pon::from_string(
+        "track_set [
+            key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]] },
+            delay { track: fixed_value { property: this.y, value: 2.0 }, by: 0.5 }
+        ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(export_csv(&*track, Duration::zero(), Duration::seconds(1), Duration::milliseconds(250)),
+        "time,this.x,this.y\n0,0,\n0.25,0.25,\n0.5,0.5,2\n0.75,0.75,2\n1,1,2\n");
+}
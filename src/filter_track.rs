@@ -0,0 +1,80 @@
+#[cfg(test)]
+use track_set::*;
+
+use time::*;
+use track::*;
+use pyramid::pon::*;
+use animatable::*;
+use event_track::*;
+
+/// Only keeps the values a track writes to properties on one entity.
+#[derive(Debug)]
+pub struct FilterTrack {
+    pub track: Box<Track>,
+    pub entity_path: EntityPath
+}
+
+impl FilterTrack {
+    fn filter(&self, values: &mut Vec<(NamedPropRef, Animatable)>) {
+        values.retain(|&(ref prop, _)| prop.entity_path == self.entity_path);
+    }
+}
+
+impl Track for FilterTrack {
+    fn value_at(&self, time: Duration) -> Vec<(NamedPropRef, Animatable)> {
+        let mut values = self.track.value_at(time);
+        self.filter(&mut values);
+        values
+    }
+    fn value_at_reusing(&self, time: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) {
+        self.track.value_at_reusing(time, out);
+        self.filter(out);
+    }
+    fn seek_reusing(&self, from: Duration, to: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) -> Vec<Event> {
+        let events = self.track.seek_reusing(from, to, out);
+        self.filter(out);
+        events
+    }
+    fn affected_properties(&self) -> Vec<NamedPropRef> {
+        self.track.affected_properties().into_iter()
+            .filter(|prop| prop.entity_path == self.entity_path)
+            .collect()
+    }
+    fn duration(&self) -> Duration {
+        self.track.duration()
+    }
+    fn is_finished(&self, time: Duration) -> bool {
+        self.track.is_finished(time)
+    }
+    fn events_between(&self, from: Duration, to: Duration) -> Vec<Event> {
+        self.track.events_between(from, to)
+    }
+}
+
+#[test]
+fn test_filter() {
+    let set: TrackSet = Pon::from_string(
+        "track_set [
+            fixed_value { property: this.x, value: 0.5 },
+            fixed_value { property: child.x, value: 0.2 },
+            fixed_value { property: this.y, value: 0.7 }
+        ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let filtered = FilterTrack { track: Box::new(set), entity_path: EntityPath::This };
+    assert_eq!(filtered.value_at(Duration::zero()), vec![
+        (NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.5)),
+        (NamedPropRef::new(EntityPath::This, "y"), Animatable::new_float(0.7))
+    ]);
+}
+
+#[test]
+fn test_filter_affected_properties() {
+    let set: TrackSet = Pon::from_string(
+        "track_set [
+            fixed_value { property: this.x, value: 0.5 },
+            fixed_value { property: child.x, value: 0.2 }
+        ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let filtered = FilterTrack { track: Box::new(set), entity_path: EntityPath::This };
+    assert_eq!(filtered.affected_properties(), this_props(&["x"]));
+}
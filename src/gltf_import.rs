@@ -0,0 +1,348 @@
+//! Imports key frame animations from glTF 2.0 files.
+//!
+//! Every channel becomes a CurveTrack writing the property `<node name>.<path>` of the animated
+//! entity, for example `arm.rotation`. Use a RetargetTrack to point them at other properties.
+
+use std::cmp;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::mem;
+use std::path::Path;
+use rustc_serialize::json::Json;
+use rustc_serialize::base64::FromBase64;
+use time::*;
+use pyramid::pon::*;
+use animatable::*;
+use curve::*;
+use curve_track::*;
+use easing::*;
+use interpolation::*;
+use spline::*;
+use track_set::*;
+use error::*;
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(io::Error),
+    Json(String),
+    /// The file is valid json but not an animation this importer understands
+    Invalid(String)
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ImportError::Io(ref err) => write!(f, "io error: {}", err),
+            ImportError::Json(ref err) => write!(f, "json error: {}", err),
+            ImportError::Invalid(ref err) => write!(f, "invalid gltf: {}", err)
+        }
+    }
+}
+
+impl Error for ImportError {
+    fn description(&self) -> &str {
+        match *self {
+            ImportError::Io(ref err) => err.description(),
+            ImportError::Json(_) => "invalid json",
+            ImportError::Invalid(_) => "invalid gltf"
+        }
+    }
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            ImportError::Io(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for ImportError {
+    fn from(err: io::Error) -> ImportError {
+        ImportError::Io(err)
+    }
+}
+
+fn invalid<T>(message: &str) -> Result<T, ImportError> {
+    Err(ImportError::Invalid(message.to_string()))
+}
+
+/// Looks up `json[key]`
+fn field<'a>(json: &'a Json, key: &str) -> Result<&'a Json, ImportError> {
+    match json.find(key) {
+        Some(value) => Ok(value),
+        None => invalid(&format!("missing {}", key))
+    }
+}
+
+fn index_field(json: &Json, key: &str) -> Result<usize, ImportError> {
+    match try!(field(json, key)).as_u64() {
+        Some(i) => Ok(i as usize),
+        None => invalid(&format!("{} is not an index", key))
+    }
+}
+
+/// Looks up `json[key][index]`
+fn element<'a>(json: &'a Json, key: &str, index: usize) -> Result<&'a Json, ImportError> {
+    match try!(field(json, key)).as_array().and_then(|arr| arr.get(index)) {
+        Some(value) => Ok(value),
+        None => invalid(&format!("{}[{}] doesn't exist", key, index))
+    }
+}
+
+fn load_buffer(buffer: &Json, base_dir: &Path) -> Result<Vec<u8>, ImportError> {
+    let uri = match try!(field(buffer, "uri")).as_string() {
+        Some(uri) => uri,
+        None => return invalid("buffer uri is not a string")
+    };
+    if uri.starts_with("data:") {
+        match uri.find(";base64,") {
+            Some(i) => uri[i + 8..].from_base64().map_err(|err| ImportError::Invalid(err.to_string())),
+            None => invalid("only base64 data uris are supported")
+        }
+    } else {
+        let mut data = vec![];
+        try!(try!(File::open(base_dir.join(uri))).read_to_end(&mut data));
+        Ok(data)
+    }
+}
+
+/// Reads a float accessor, as one Vec<f32> per element
+fn read_accessor(gltf: &Json, buffers: &[Vec<u8>], index: usize) -> Result<Vec<Vec<f32>>, ImportError> {
+    let accessor = try!(element(gltf, "accessors", index));
+    if try!(index_field(accessor, "componentType")) != 5126 {
+        return invalid("only float accessors are supported");
+    }
+    let components = match try!(field(accessor, "type")).as_string() {
+        Some("SCALAR") => 1,
+        Some("VEC2") => 2,
+        Some("VEC3") => 3,
+        Some("VEC4") => 4,
+        _ => return invalid("unsupported accessor type")
+    };
+    let count = try!(index_field(accessor, "count"));
+    let view = try!(element(gltf, "bufferViews", try!(index_field(accessor, "bufferView"))));
+    let buffer = match buffers.get(try!(index_field(view, "buffer"))) {
+        Some(buffer) => buffer,
+        None => return invalid("buffer view refers to a missing buffer")
+    };
+    let offset = index_field(view, "byteOffset").unwrap_or(0) + index_field(accessor, "byteOffset").unwrap_or(0);
+    let stride = index_field(view, "byteStride").unwrap_or(components * 4);
+    let mut res = vec![];
+    for i in 0..count {
+        let mut element = vec![];
+        for c in 0..components {
+            let at = offset + i * stride + c * 4;
+            if at + 4 > buffer.len() {
+                return invalid("accessor reads past the end of its buffer");
+            }
+            let bits = (buffer[at] as u32) | (buffer[at + 1] as u32) << 8 | (buffer[at + 2] as u32) << 16 | (buffer[at + 3] as u32) << 24;
+            element.push(unsafe { mem::transmute::<u32, f32>(bits) });
+        }
+        res.push(element);
+    }
+    Ok(res)
+}
+
+/// Keys with glTF cubic spline tangents, which are given per unit of time
+#[derive(Debug)]
+struct CubicSplineCurve {
+    times: Vec<f32>,
+    /// (in tangent, value, out tangent) for each key
+    values: Vec<(Animatable, Animatable, Animatable)>,
+    normalize: bool
+}
+
+impl Curve<Animatable> for CubicSplineCurve {
+    fn value(&self, time: f32) -> Animatable {
+        let last = self.times.len() - 1;
+        if time <= self.times[0] {
+            return self.values[0].1.clone();
+        } else if time >= self.times[last] {
+            return self.values[last].1.clone();
+        }
+        let i = self.times.iter().position(|&t| t > time).unwrap() - 1;
+        let d = self.times[i + 1] - self.times[i];
+        let s = (time - self.times[i]) / d;
+        let (a, b) = (&self.values[i], &self.values[i + 1]);
+        let value: Vec<f32> = (0..(a.1).value.len())
+            .map(|c| hermite((a.1).value[c], (a.2).value[c] * d, (b.1).value[c], (b.0).value[c] * d, s))
+            .collect();
+        if self.normalize {
+            let len = value.iter().fold(0.0, |sum, x| sum + x * x).sqrt();
+            Animatable::new(value.iter().map(|x| x / len).collect())
+        } else {
+            Animatable::new(value)
+        }
+    }
+    fn domain(&self) -> (f32, f32) {
+        (self.times[0], self.times[self.times.len() - 1])
+    }
+}
+
+fn import_channel(gltf: &Json, animation: &Json, channel: &Json, buffers: &[Vec<u8>]) -> Result<CurveTrack, ImportError> {
+    let target = try!(field(channel, "target"));
+    let node_index = try!(index_field(target, "node"));
+    let node = try!(element(gltf, "nodes", node_index));
+    let node_name = match node.find("name").and_then(|name| name.as_string()) {
+        Some(name) => name.to_string(),
+        None => format!("node{}", node_index)
+    };
+    let path = match try!(field(target, "path")).as_string() {
+        Some(path @ "translation") | Some(path @ "rotation") | Some(path @ "scale") => path,
+        _ => return invalid("only translation, rotation and scale channels are supported")
+    };
+    let sampler = try!(element(animation, "samplers", try!(index_field(channel, "sampler"))));
+    let times: Vec<f32> = try!(read_accessor(gltf, buffers, try!(index_field(sampler, "input")))).into_iter().map(|t| t[0]).collect();
+    let values: Vec<Animatable> = try!(read_accessor(gltf, buffers, try!(index_field(sampler, "output")))).into_iter().map(Animatable::new).collect();
+    if times.is_empty() {
+        return invalid("sampler has no keys");
+    }
+    let is_rotation = path == "rotation";
+    let interpolation = match sampler.find("interpolation").and_then(|i| i.as_string()) {
+        None | Some("LINEAR") => if is_rotation { Interpolation::Slerp } else { Interpolation::Linear },
+        Some("STEP") => Interpolation::Step,
+        Some("CUBICSPLINE") => {
+            if values.len() != times.len() * 3 {
+                return invalid("cubic spline samplers need three outputs per key");
+            }
+            let values = values.chunks(3).map(|v| (v[0].clone(), v[1].clone(), v[2].clone())).collect();
+            return Ok(channel_track(&node_name, path, &times, Box::new(CubicSplineCurve { times: times.clone(), values: values, normalize: is_rotation })));
+        },
+        _ => return invalid("unknown sampler interpolation")
+    };
+    if values.len() != times.len() {
+        return invalid("sampler input and output have different lengths");
+    }
+    let keys = times.iter().zip(values.into_iter()).map(|(&t, v)| Key(t, v)).collect();
+    let curve = InterpolatedKeyFrameCurve::new_boxed(LinearKeyFrameCurve::new(keys), interpolation, vec![], Easing::Linear);
+    Ok(channel_track(&node_name, path, &times, curve))
+}
+
+fn channel_track(node_name: &str, path: &str, times: &[f32], curve: Box<Curve<Animatable>>) -> CurveTrack {
+    let mut track = CurveTrack::new_fixed_value(
+        NamedPropRef::new(EntityPath::This, &format!("{}.{}", node_name, path)), Animatable::new(vec![]));
+    track.curve = curve;
+    track.loop_type = Loop::Once;
+    // Channels with a single key, or all keys at 0, still need a duration to play for
+    track.duration = cmp::max(Duration::milliseconds((times[times.len() - 1] * 1000.0) as i64), Duration::milliseconds(1));
+    track
+}
+
+/// Imports animation number animation_index from a glTF json string. External buffers are
+/// loaded relative to base_dir.
+pub fn import_gltf_animation_from_str(json: &str, base_dir: &Path, animation_index: usize) -> Result<TrackSet, AnimationError> {
+    let gltf = try!(Json::from_str(json).map_err(|err| ImportError::Json(err.to_string())));
+    let mut buffers = vec![];
+    if let Some(arr) = gltf.find("buffers").and_then(|b| b.as_array()) {
+        for buffer in arr {
+            buffers.push(try!(load_buffer(buffer, base_dir)));
+        }
+    }
+    let animation = try!(element(&gltf, "animations", animation_index));
+    let channels = match try!(field(animation, "channels")).as_array() {
+        Some(channels) => channels,
+        None => return Err(AnimationError::from(ImportError::Invalid("channels is not an array".to_string())))
+    };
+    let mut tracks = vec![];
+    for channel in channels {
+        tracks.push(TrackSetEntry::new(Box::new(try!(import_channel(&gltf, animation, channel, &buffers)))));
+    }
+    Ok(TrackSet::from_entries(tracks))
+}
+
+/// Imports animation number animation_index from the .gltf file at path
+pub fn import_gltf_animation(path: &Path, animation_index: usize) -> Result<TrackSet, AnimationError> {
+    let mut json = String::new();
+    try!(try!(File::open(path).map_err(ImportError::from)).read_to_string(&mut json).map_err(ImportError::from));
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    import_gltf_animation_from_str(&json, base_dir, animation_index)
+}
+
+#[cfg(test)]
+const TEST_GLTF: &'static str = r#"{
+    "asset": { "version": "2.0" },
+    "nodes": [{ "name": "arm" }, { "name": "leg" }],
+    "buffers": [{ "byteLength": 64, "uri": "data:application/octet-stream;base64,AAAAAAAAgD8AAAAAAAAAAAAAAAAAAABAAACAQAAAwEAAAAAAAAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/AAAAAA==" },
+        { "byteLength": 72, "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAQAAAAAAAAAAAAAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAAAAAAAAA" }],
+    "bufferViews": [{ "buffer": 0, "byteLength": 64 }, { "buffer": 1, "byteLength": 72 }],
+    "accessors": [
+        { "bufferView": 0, "componentType": 5126, "count": 2, "type": "SCALAR" },
+        { "bufferView": 0, "byteOffset": 8, "componentType": 5126, "count": 2, "type": "VEC3" },
+        { "bufferView": 0, "byteOffset": 32, "componentType": 5126, "count": 2, "type": "VEC4" },
+        { "bufferView": 1, "componentType": 5126, "count": 6, "type": "VEC3" }
+    ],
+    "animations": [{
+        "samplers": [
+            { "input": 0, "output": 1, "interpolation": "LINEAR" },
+            { "input": 0, "output": 2 },
+            { "input": 0, "output": 1, "interpolation": "STEP" },
+            { "input": 0, "output": 3, "interpolation": "CUBICSPLINE" }
+        ],
+        "channels": [
+            { "sampler": 0, "target": { "node": 0, "path": "translation" } },
+            { "sampler": 1, "target": { "node": 0, "path": "rotation" } },
+            { "sampler": 2, "target": { "node": 0, "path": "scale" } },
+            { "sampler": 3, "target": { "node": 1, "path": "translation" } }
+        ]
+    }]
+}"#;
+
+#[test]
+fn test_import_gltf() {
+    use track::*;
+    let set = import_gltf_animation_from_str(TEST_GLTF, Path::new("."), 0).unwrap();
+    assert_eq!(set.duration(), Duration::seconds(1));
+    let values = set.value_at(Duration::milliseconds(500));
+    assert_eq!(values[0], (NamedPropRef::new(EntityPath::This, "arm.translation"), Animatable::new(vec![1.0, 2.0, 3.0])));
+    assert_eq!(values[2], (NamedPropRef::new(EntityPath::This, "arm.scale"), Animatable::new(vec![0.0, 0.0, 0.0])));
+    let half = 0.5f32.sqrt();
+    assert_eq!(values[1].0, NamedPropRef::new(EntityPath::This, "arm.rotation"));
+    for (x, y) in values[1].1.value.iter().zip(vec![0.0, 0.0, half, half].iter()) {
+        assert!((x - y).abs() < 0.0001);
+    }
+    assert_eq!(set.value_at(Duration::seconds(1))[0].1, Animatable::new(vec![2.0, 4.0, 6.0]));
+}
+
+#[test]
+fn test_import_gltf_cubic_spline() {
+    use track::*;
+    let set = import_gltf_animation_from_str(TEST_GLTF, Path::new("."), 0).unwrap();
+    // From 0 to 1 leaving the first key with a slope of 2 and arriving flat, so ahead of linear halfway
+    let leg = |ms| set.value_at(Duration::milliseconds(ms)).pop().unwrap();
+    assert_eq!(leg(500).0, NamedPropRef::new(EntityPath::This, "leg.translation"));
+    assert_eq!(leg(500).1, Animatable::new(vec![0.75, 0.0, 0.0]));
+    assert_eq!(leg(1000).1, Animatable::new(vec![1.0, 0.0, 0.0]));
+}
+
+#[test]
+fn test_import_gltf_missing_animation() {
+    match import_gltf_animation_from_str(TEST_GLTF, Path::new("."), 1) {
+        Err(AnimationError::Import(ImportError::Invalid(_))) => {},
+        r @ _ => panic!("Expected Invalid, got {:?}", r.map(|set| set.tracks.len()))
+    }
+}
+
+#[test]
+fn test_import_gltf_single_key() {
+    use track::*;
+    let json = r#"{
+        "asset": { "version": "2.0" },
+        "nodes": [{ "name": "arm" }],
+        "buffers": [{ "byteLength": 64, "uri": "data:application/octet-stream;base64,AAAAAAAAgD8AAAAAAAAAAAAAAAAAAABAAACAQAAAwEAAAAAAAAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/AAAAAA==" }],
+        "bufferViews": [{ "buffer": 0, "byteLength": 64 }],
+        "accessors": [
+            { "bufferView": 0, "componentType": 5126, "count": 1, "type": "SCALAR" },
+            { "bufferView": 0, "byteOffset": 8, "componentType": 5126, "count": 1, "type": "VEC3" }
+        ],
+        "animations": [{
+            "samplers": [{ "input": 0, "output": 1 }],
+            "channels": [{ "sampler": 0, "target": { "node": 0, "path": "translation" } }]
+        }]
+    }"#;
+    let set = import_gltf_animation_from_str(json, Path::new("."), 0).unwrap();
+    assert_eq!(set.duration(), Duration::milliseconds(1));
+    assert_eq!(set.value_at(Duration::zero()), vec![(NamedPropRef::new(EntityPath::This, "arm.translation"), Animatable::new(vec![0.0, 0.0, 0.0]))]);
+    assert_eq!(set.value_at(Duration::milliseconds(500)), vec![]);
+}
@@ -0,0 +1,380 @@
+#[cfg(test)]
+use curve_track::*;
+#[cfg(test)]
+use track::*;
+#[cfg(test)]
+use time::*;
+
+use std::f32::consts::PI;
+use pyramid::pon::*;
+use curve::*;
+use easing::*;
+use animatable::*;
+
+/// How the values of two keys are blended.
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Interpolation {
+    /// Componentwise linear interpolation
+    Linear,
+    /// Spherical linear interpolation of four component quaternions, along the shortest path
+    Slerp,
+    /// Treats three or four component values as rgb(a) colors and interpolates them in hsv space,
+    /// taking the shortest way around the hue circle
+    Hsv,
+    /// Treats three or four component values as srgb(a) colors and interpolates the color in
+    /// linear light, so midtones don't come out too dark. Alpha is interpolated as is.
+    SrgbLinear,
+    /// Treats every component as an angle in radians, and turns the short way around
+    Angle,
+    /// Holds the value of the first key until the next key
+    Step
+}
+
+fn normalized(value: Vec<f32>) -> Animatable {
+    let len = value.iter().fold(0.0, |sum, x| sum + x * x).sqrt();
+    Animatable::new(value.iter().map(|x| x / len).collect())
+}
+
+fn slerp(a: &Animatable, b: &Animatable, p: f32) -> Animatable {
+    let mut dot = a.value.iter().zip(b.value.iter()).fold(0.0, |sum, (x, y)| sum + x * y);
+    // q and -q are the same rotation, so flip b if that makes the path shorter
+    let b: Vec<f32> = if dot < 0.0 {
+        dot = -dot;
+        b.value.iter().map(|x| -x).collect()
+    } else {
+        b.value.clone()
+    };
+    if dot > 0.9995 {
+        let lerped = a.value.iter().zip(b.iter()).map(|(x, y)| x * (1.0 - p) + y * p).collect();
+        return normalized(lerped);
+    }
+    let theta = dot.min(1.0).acos();
+    let wa = ((1.0 - p) * theta).sin() / theta.sin();
+    let wb = (p * theta).sin() / theta.sin();
+    normalized(a.value.iter().zip(b.iter()).map(|(x, y)| x * wa + y * wb).collect())
+}
+
+/// Converts rgb in 0..1 to hue in 0..360 degrees, saturation and value
+fn rgb_to_hsv(r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let saturation = if max == 0.0 { 0.0 } else { delta / max };
+    ((hue + 360.0) % 360.0, saturation, max)
+}
+
+fn hsv_to_rgb(h: f32, s: f32, v: f32) -> (f32, f32, f32) {
+    let c = v * s;
+    let h = h / 60.0;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x)
+    };
+    let m = v - c;
+    (r + m, g + m, b + m)
+}
+
+fn hsv_interpolate(a: &Animatable, b: &Animatable, p: f32) -> Animatable {
+    let (ha, sa, va) = rgb_to_hsv(a.value[0], a.value[1], a.value[2]);
+    let (hb, sb, vb) = rgb_to_hsv(b.value[0], b.value[1], b.value[2]);
+    // A grey has no hue of its own, so borrow the other color's to avoid sweeping through red
+    let ha = if sa == 0.0 { hb } else { ha };
+    let hb = if sb == 0.0 { ha } else { hb };
+    let mut dh = hb - ha;
+    if dh > 180.0 {
+        dh -= 360.0;
+    } else if dh < -180.0 {
+        dh += 360.0;
+    }
+    let h = (ha + dh * p + 360.0) % 360.0;
+    let (r, g, b_) = hsv_to_rgb(h, sa + (sb - sa) * p, va + (vb - va) * p);
+    let mut res = vec![r, g, b_];
+    if a.value.len() == 4 {
+        res.push(a.value[3] + (b.value[3] - a.value[3]) * p);
+    }
+    Animatable::new(res)
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 }
+}
+
+fn srgb_linear_interpolate(a: &Animatable, b: &Animatable, p: f32) -> Animatable {
+    Animatable::new(a.value.iter().zip(b.value.iter()).enumerate().map(|(i, (&x, &y))| {
+        if i < 3 {
+            let (x, y) = (srgb_to_linear(x), srgb_to_linear(y));
+            linear_to_srgb(x + (y - x) * p)
+        } else {
+            x + (y - x) * p
+        }
+    }).collect())
+}
+
+fn angle_interpolate(a: &Animatable, b: &Animatable, p: f32) -> Animatable {
+    Animatable::new(a.value.iter().zip(b.value.iter()).map(|(x, y)| {
+        let mut delta = (y - x) % (2.0 * PI);
+        if delta > PI {
+            delta -= 2.0 * PI;
+        } else if delta <= -PI {
+            delta += 2.0 * PI;
+        }
+        x + delta * p
+    }).collect())
+}
+
+impl Interpolation {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Interpolation::Linear => "linear",
+            Interpolation::Slerp => "slerp",
+            Interpolation::Hsv => "hsv",
+            Interpolation::SrgbLinear => "srgb_linear",
+            Interpolation::Angle => "angle",
+            Interpolation::Step => "step"
+        }
+    }
+    /// Blends a and b, falling back to a if that gives NaN or infinite values
+    pub fn interpolate(&self, a: &Animatable, b: &Animatable, p: f32) -> Animatable {
+        let res = match *self {
+            Interpolation::Slerp if a.value.len() == 4 && b.value.len() == 4 => slerp(a, b, p),
+            Interpolation::Hsv if a.value.len() == b.value.len() && (a.value.len() == 3 || a.value.len() == 4) =>
+                hsv_interpolate(a, b, p),
+            Interpolation::SrgbLinear if a.value.len() == b.value.len() && (a.value.len() == 3 || a.value.len() == 4) =>
+                srgb_linear_interpolate(a, b, p),
+            Interpolation::Angle => angle_interpolate(a, b, p),
+            Interpolation::Step => a.clone(),
+            _ => Interpolateable::interpolate(a, b, &p)
+        };
+        if res.is_finite() { res } else { a.clone() }
+    }
+}
+
+/// A key frame that can pick the interpolation of the segment starting at it
+#[derive(PartialEq, Debug, Clone)]
+pub struct InterpolatedKey {
+    pub key: Key<Animatable>,
+    /// None uses the interpolation of the curve
+    pub interpolation: Option<Interpolation>
+}
+
+/// A key frame curve that blends the keys using an interpolation other than linear.
+#[derive(PartialEq, Debug)]
+pub struct InterpolatedKeyFrameCurve {
+    pub curve: LinearKeyFrameCurve<Animatable>,
+    pub interpolation: Interpolation,
+    /// Interpolation of the segment starting at each key, overriding the curve's.
+    /// Keys past the end of the list use the curve's interpolation.
+    pub key_interpolations: Vec<Option<Interpolation>>,
+    pub easing: Easing
+}
+
+impl InterpolatedKeyFrameCurve {
+    pub fn new_boxed(curve: LinearKeyFrameCurve<Animatable>, interpolation: Interpolation,
+                     key_interpolations: Vec<Option<Interpolation>>, easing: Easing) -> Box<Curve<Animatable>> {
+        if interpolation == Interpolation::Linear && key_interpolations.iter().all(|i| i.is_none()) {
+            return EasedKeyFrameCurve::new_boxed(curve, easing);
+        }
+        Box::new(InterpolatedKeyFrameCurve {
+            curve: curve,
+            interpolation: interpolation,
+            key_interpolations: key_interpolations,
+            easing: easing
+        })
+    }
+    /// The interpolation used between key i and key i + 1
+    pub fn segment_interpolation(&self, i: usize) -> Interpolation {
+        match self.key_interpolations.get(i) {
+            Some(&Some(interpolation)) => interpolation,
+            _ => self.interpolation
+        }
+    }
+}
+
+impl Curve<Animatable> for InterpolatedKeyFrameCurve {
+    fn value(&self, time: f32) -> Animatable {
+        let (a, b, p) = self.curve.segment(time);
+        let interpolation = match self.curve.keys_at_or_before(time) {
+            0 => self.interpolation,
+            n => self.segment_interpolation(n - 1)
+        };
+        interpolation.interpolate(a, b, self.easing.apply(p))
+    }
+    fn stepped_value(&self, time: f32) -> Animatable {
+        self.curve.stepped_value(time)
+    }
+    fn key_times(&self) -> Vec<f32> {
+        self.curve.key_times()
+    }
+    fn to_pon(&self) -> Option<Pon> where Animatable: ToPon {
+        let keys = Pon::Array(self.curve.keys().iter().enumerate().map(|(i, key)| {
+            match self.key_interpolations.get(i) {
+                Some(&Some(interpolation)) => object_pon(vec![
+                    ("time", Pon::Number(key.0)),
+                    ("value", key.1.to_pon()),
+                    ("interp", Pon::String(interpolation.name().to_string()))
+                ]),
+                _ => Pon::Array(vec![Pon::Number(key.0), key.1.to_pon()])
+            }
+        }).collect());
+        Some(self.curve.key_framed_pon(vec![
+            ("keys", keys),
+            ("easing", Pon::String(self.easing.name().to_string())),
+            ("interp", Pon::String(self.interpolation.name().to_string()))
+        ]))
+    }
+    fn to_data(&self) -> Option<CurveData<Animatable>> where Animatable: Clone {
+        Some(CurveData::KeyFramed {
+            keys: self.curve.keys().to_vec(),
+            easing: self.easing,
+            interpolation: self.interpolation,
+            key_interpolations: self.key_interpolations.clone(),
+            cycle_period: self.curve.cycle_period()
+        })
+    }
+    fn domain(&self) -> (f32, f32) {
+        self.curve.domain()
+    }
+}
+
+impl Translatable<InterpolatedKey> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<InterpolatedKey, PonTranslateErr> {
+        let interpolation = match self {
+            &Pon::Object(..) => match self.field("interp") {
+                Ok(interpolation) => Some(try!(interpolation.translate::<Interpolation>(context))),
+                Err(_) => None
+            },
+            _ => None
+        };
+        Ok(InterpolatedKey {
+            key: try!(self.translate::<Key<Animatable>>(context)),
+            interpolation: interpolation
+        })
+    }
+}
+
+impl Translatable<Interpolation> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<Interpolation, PonTranslateErr> {
+        match try!(self.translate::<String>(context)).as_str() {
+            "linear" => Ok(Interpolation::Linear),
+            "slerp" => Ok(Interpolation::Slerp),
+            "hsv" => Ok(Interpolation::Hsv),
+            "srgb_linear" => Ok(Interpolation::SrgbLinear),
+            "angle" => Ok(Interpolation::Angle),
+            "step" => Ok(Interpolation::Step),
+            _ => Err(PonTranslateErr::InvalidValue { value: format!("{:?}", self) })
+        }
+    }
+}
+
+#[cfg(test)]
+fn assert_near(a: &Animatable, b: &Animatable) {
+    assert_eq!(a.value.len(), b.value.len());
+    for (x, y) in a.value.iter().zip(b.value.iter()) {
+        assert!((x - y).abs() < 0.0001, "{:?} != {:?}", a, b);
+    }
+}
+
+#[test]
+fn test_slerp_half_turn() {
+    let track: CurveTrack = Pon::from_string(
+        "key_framed { property: this.rotation, keys: [[0.0, [0.0, 0.0, 0.0, 1.0]], [1.0, [0.0, 0.0, 1.0, 0.0]]], interp: 'slerp' }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let half = 0.5f32.sqrt();
+    let mid = track.value_at(Duration::milliseconds(500)).pop().unwrap().1;
+    assert_near(&mid, &Animatable::new(vec![0.0, 0.0, half, half]));
+    let len = mid.value.iter().fold(0.0, |sum, x| sum + x * x).sqrt();
+    assert!((len - 1.0).abs() < 0.0001);
+}
+
+#[test]
+fn test_slerp_shortest_path() {
+    let quarter = 45.0f32.to_radians();
+    let a = Animatable::new(vec![0.0, 0.0, 0.0, 1.0]);
+    // The same 90 degree rotation as (0, 0, sin 45, cos 45), but in the opposite hemisphere
+    let b = Animatable::new(vec![0.0, 0.0, -quarter.sin(), -quarter.cos()]);
+    let eighth = 22.5f32.to_radians();
+    assert_near(&Interpolation::Slerp.interpolate(&a, &b, 0.5), &Animatable::new(vec![0.0, 0.0, eighth.sin(), eighth.cos()]));
+}
+
+#[test]
+fn test_hsv_red_to_green() {
+    let track: CurveTrack = Pon::from_string(
+        "key_framed { property: this.color, keys: [[0.0, [1.0, 0.0, 0.0]], [1.0, [0.0, 1.0, 0.0]]], interp: 'hsv' }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let mid = track.value_at(Duration::milliseconds(500)).pop().unwrap().1;
+    assert_near(&mid, &Animatable::new(vec![1.0, 1.0, 0.0]));
+}
+
+#[test]
+fn test_hsv_hue_wraps() {
+    // Magenta (300 degrees) to red (0 degrees) goes forward through 330 degrees, not back through green
+    let a = Animatable::new(vec![1.0, 0.0, 1.0, 0.0]);
+    let b = Animatable::new(vec![1.0, 0.0, 0.0, 1.0]);
+    assert_near(&Interpolation::Hsv.interpolate(&a, &b, 0.5), &Animatable::new(vec![1.0, 0.0, 0.5, 0.5]));
+}
+
+#[test]
+fn test_srgb_linear_midpoint() {
+    let track: CurveTrack = Pon::from_string(
+        "key_framed { property: this.color, keys: [[0.0, [0.0, 0.0, 0.0, 0.0]], [1.0, [1.0, 1.0, 1.0, 1.0]]], interp: 'srgb_linear' }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let mid = track.value_at(Duration::milliseconds(500)).pop().unwrap().1;
+    // Half the light of white is about 0.735 in srgb, brighter than the naive 0.5. Alpha stays linear.
+    assert_near(&mid, &Animatable::new(vec![0.7354, 0.7354, 0.7354, 0.5]));
+    assert_near(&Interpolation::SrgbLinear.interpolate(&mid, &mid, 0.3), &mid);
+}
+
+#[test]
+fn test_angle_short_path() {
+    let track: CurveTrack = Pon::from_string(
+        "key_framed { property: this.rotation, keys: [[0.0, 6.1], [1.0, 0.1]], interp: 'angle' }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let short = 2.0 * PI - 6.0;
+    let mid = track.value_at(Duration::milliseconds(500)).pop().unwrap().1;
+    assert_near(&mid, &Animatable::new_float(6.1 + short * 0.5));
+    let later = track.value_at(Duration::milliseconds(750)).pop().unwrap().1;
+    assert!(later.value[0] > mid.value[0]);
+    assert_near(&later, &Animatable::new_float(6.1 + short * 0.75));
+}
+
+#[test]
+fn test_per_key_interpolation() {
+    let keys = translate_keys::<InterpolatedKey>(&Pon::from_string(
+        "[[0.0, 0.0], { time: 1.0, value: 1.0, interp: 'step' }, { time: 2.0, value: 3.0 }, [3.0, 0.0]]").unwrap(),
+        &mut TranslateContext::empty()).unwrap();
+    let modes: Vec<Option<Interpolation>> = keys.iter().map(|k| k.interpolation).collect();
+    assert_eq!(modes, vec![None, Some(Interpolation::Step), None, None]);
+    assert_eq!(keys[1].key, Key(1.0, Animatable::new_float(1.0)));
+
+    let track: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], { time: 1.0, value: 1.0, interp: 'step' }, [2.0, 3.0]], duration: 2.0 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(track.value_at(Duration::milliseconds(500)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.5))]);
+    assert_eq!(track.value_at(Duration::milliseconds(1500)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(1.0))]);
+}
+
+#[test]
+fn test_unknown_key_interpolation() {
+    let res = translate_keys::<InterpolatedKey>(&Pon::from_string("[{ time: 1.0, value: 1.0, interp: 'wobbly' }]").unwrap(),
+        &mut TranslateContext::empty());
+    assert!(res.is_err());
+}
@@ -0,0 +1,106 @@
+
+use time::*;
+use track::*;
+use pyramid::pon::*;
+use animatable::*;
+use event_track::*;
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum InvertMode {
+    /// -value
+    Negate,
+    /// 1 - value, for properties in the 0 to 1 range
+    Complement
+}
+
+/// Inverts every value a track produces.
+#[derive(Debug)]
+pub struct InvertTrack {
+    pub track: Box<Track>,
+    pub mode: InvertMode
+}
+
+impl InvertTrack {
+    fn invert(&self, values: &mut [(NamedPropRef, Animatable)]) {
+        for update in values.iter_mut() {
+            let value = match self.mode {
+                InvertMode::Negate => update.1.value.iter().map(|x| -x).collect(),
+                InvertMode::Complement => update.1.value.iter().map(|x| 1.0 - x).collect()
+            };
+            update.1 = Animatable::new(value);
+        }
+    }
+}
+
+impl Track for InvertTrack {
+    fn value_at(&self, time: Duration) -> Vec<(NamedPropRef, Animatable)> {
+        let mut values = self.track.value_at(time);
+        self.invert(&mut values);
+        values
+    }
+    fn value_at_reusing(&self, time: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) {
+        self.track.value_at_reusing(time, out);
+        self.invert(out);
+    }
+    fn seek_reusing(&self, from: Duration, to: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) -> Vec<Event> {
+        let events = self.track.seek_reusing(from, to, out);
+        self.invert(out);
+        events
+    }
+    fn affected_properties(&self) -> Vec<NamedPropRef> {
+        self.track.affected_properties()
+    }
+    fn duration(&self) -> Duration {
+        self.track.duration()
+    }
+    fn is_finished(&self, time: Duration) -> bool {
+        self.track.is_finished(time)
+    }
+    fn events_between(&self, from: Duration, to: Duration) -> Vec<Event> {
+        self.track.events_between(from, to)
+    }
+}
+
+impl Translatable<InvertMode> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<InvertMode, PonTranslateErr> {
+        match try!(self.translate::<String>(context)).as_str() {
+            "negate" => Ok(InvertMode::Negate),
+            "complement" => Ok(InvertMode::Complement),
+            _ => Err(PonTranslateErr::InvalidValue { value: format!("{:?}", self) })
+        }
+    }
+}
+
+impl Translatable<InvertTrack> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<InvertTrack, PonTranslateErr> {
+        self.as_typed(|&TypedPon { ref type_name, ref data }| {
+            match type_name.as_str() {
+                "invert" => {
+                    Ok(InvertTrack {
+                        track: try!(data.field_as::<Box<Track>>("track", context)),
+                        mode: try!(data.field_as_or("mode", InvertMode::Negate, context))
+                    })
+                },
+                s @ _ => Err(PonTranslateErr::UnrecognizedType(s.to_string()))
+            }
+        })
+    }
+}
+
+#[test]
+fn test_invert_complement() {
+    let inverted: InvertTrack = Pon::from_string(
+        "invert { track: key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]] }, mode: 'complement' }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(inverted.value_at(Duration::zero()), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(1.0))]);
+    assert_eq!(inverted.value_at(Duration::milliseconds(250)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.75))]);
+    assert_eq!(inverted.value_at(Duration::seconds(1)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.0))]);
+}
+
+#[test]
+fn test_invert_negate() {
+    let inverted: InvertTrack = Pon::from_string(
+        "invert { track: key_framed { property: this.x, keys: [[0.0, [0.0, 2.0]], [1.0, [1.0, 4.0]]] } }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(inverted.value_at(Duration::milliseconds(500)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new(vec![-0.5, -3.0]))]);
+}
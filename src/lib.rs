@@ -1,18 +1,50 @@
 #![feature(convert, box_patterns)]
+#![cfg_attr(feature = "serialize", feature(custom_derive, plugin))]
+#![cfg_attr(feature = "serialize", plugin(serde_macros))]
 #[macro_use]
 extern crate pyramid;
 extern crate time;
 extern crate cgmath;
+#[cfg(feature = "gltf")]
+extern crate rustc_serialize;
+#[cfg(feature = "serialize")]
+extern crate serde;
+#[cfg(all(feature = "serialize", test))]
+extern crate serde_json;
+#[cfg(feature = "parallel")]
+extern crate rayon;
 
 use std::collections::HashMap;
-use std::collections::hash_map::Entry;
 
 pub mod animatable;
+pub mod common;
 pub mod track;
 pub mod curve_track;
 pub mod track_set;
 pub mod weighted_tracks;
+pub mod event_track;
+pub mod crossfade_track;
+pub mod sequence_track;
+pub mod delay_track;
+pub mod repeat_track;
+pub mod retarget_track;
+pub mod filter_track;
+pub mod time_remap_track;
+pub mod invert_track;
+pub mod clamp_track;
+pub mod sprite_track;
+pub mod split_track;
+pub mod euler_rotation_track;
+pub mod bool_track;
 pub mod curve;
+pub mod curve_ops;
+pub mod easing;
+pub mod spline;
+pub mod interpolation;
+pub mod export;
+pub mod error;
+#[cfg(feature = "gltf")]
+pub mod gltf_import;
 
 use time::*;
 
@@ -24,26 +56,65 @@ use pyramid::system::*;
 pub use track::*;
 pub use track_set::*;
 pub use curve_track::*;
+pub use event_track::*;
+pub use crossfade_track::*;
+pub use sequence_track::*;
+pub use delay_track::*;
+pub use repeat_track::*;
+pub use retarget_track::*;
+pub use filter_track::*;
+pub use time_remap_track::*;
+pub use invert_track::*;
+pub use clamp_track::*;
+pub use sprite_track::*;
+pub use split_track::*;
+pub use euler_rotation_track::*;
+pub use bool_track::*;
 pub use curve::*;
+pub use curve_ops::*;
+pub use easing::*;
+pub use spline::*;
+pub use interpolation::*;
+pub use export::*;
+pub use error::*;
+#[cfg(feature = "gltf")]
+pub use gltf_import::*;
 pub use animatable::*;
 
 struct EntityAnimation {
     track: Box<Track>,
-    cached_resolved_named_prop_refs: HashMap<NamedPropRef, PropRef>
+    cached_resolved_named_prop_refs: HashMap<NamedPropRef, PropRef>,
+    /// The time of the last update, the next update seeks from here
+    previous_time: Duration,
+    /// The values written by the last update, reused by the next one
+    values: Vec<(NamedPropRef, Animatable)>
 }
 
 pub struct AnimationSubSystem {
     animations: HashMap<EntityId, EntityAnimation>,
-    start_time: Timespec
+    start_time: Timespec,
+    /// The events fired by the last update, replaced by every update
+    events: Vec<(EntityId, Event)>
 }
 
 impl AnimationSubSystem {
     pub fn new() -> AnimationSubSystem {
         AnimationSubSystem {
             animations: HashMap::new(),
-            start_time: time::get_time()
+            start_time: time::get_time(),
+            events: Vec::new()
         }
     }
+    /// The events fired by the animations in the last update, in the order they were fired.
+    /// Every update drops the events of the one before it, so whoever reacts to events has to read
+    /// them after each update.
+    pub fn events(&self) -> &[(EntityId, Event)] {
+        &self.events
+    }
+    /// Like events, but moves the events out
+    pub fn take_events(&mut self) -> Vec<(EntityId, Event)> {
+        std::mem::replace(&mut self.events, Vec::new())
+    }
 }
 
 
@@ -61,7 +132,9 @@ impl ISubSystem for AnimationSubSystem {
                             Ok(anim) => {
                                 self.animations.insert(pr.entity_id, EntityAnimation {
                                     track: anim,
-                                    cached_resolved_named_prop_refs: HashMap::new()
+                                    cached_resolved_named_prop_refs: HashMap::new(),
+                                    previous_time: time::get_time() - self.start_time,
+                                    values: vec![]
                                 });
                             },
                             Err(err) => { println!("Failed to translate animation: {:?}", err.to_string()); }
@@ -74,13 +147,20 @@ impl ISubSystem for AnimationSubSystem {
     }
     fn update(&mut self, system: &mut System) {
         let time = time::get_time() - self.start_time;
+        self.events.clear();
         for (entity_id, entity_animation) in self.animations.iter_mut() {
-            let to_update = { entity_animation.track.value_at(time) };
-            for (named_prop_ref, value) in to_update {
-                let target = match entity_animation.cached_resolved_named_prop_refs.entry(named_prop_ref.clone()) {
-                    Entry::Occupied(o) => o.into_mut(),
-                    Entry::Vacant(v) => v.insert(system.document().resolve_named_prop_ref(entity_id, &named_prop_ref).unwrap())
-                };
+            let events = entity_animation.track.seek_reusing(entity_animation.previous_time, time, &mut entity_animation.values);
+            entity_animation.previous_time = time;
+            for event in events {
+                self.events.push((*entity_id, event));
+            }
+            let cache = &mut entity_animation.cached_resolved_named_prop_refs;
+            for &(ref named_prop_ref, ref value) in entity_animation.values.iter() {
+                // Looked up by reference, so property refs are only cloned the first time they're resolved
+                if !cache.contains_key(named_prop_ref) {
+                    cache.insert(named_prop_ref.clone(), system.document().resolve_named_prop_ref(entity_id, named_prop_ref).unwrap());
+                }
+                let target = &cache[named_prop_ref];
                 system.document_mut().set_property(&target.entity_id.clone(), &target.property_key, value.to_pon()).unwrap();
             }
         }
@@ -0,0 +1,197 @@
+
+use std::cmp;
+use time::*;
+use track::*;
+use pyramid::pon::*;
+use animatable::*;
+use event_track::*;
+
+/// Repeats a track a number of times, or forever, regardless of how the track itself loops.
+#[derive(Debug)]
+pub struct RepeatTrack {
+    pub track: Box<Track>,
+    /// None repeats forever
+    pub times: Option<u32>
+}
+
+impl RepeatTrack {
+    /// Length of one repetition, or None if the inner track can't be repeated
+    fn cycle_ms(&self) -> Option<i64> {
+        let duration = self.track.duration();
+        if duration == infinite_duration() || duration <= Duration::zero() {
+            None
+        } else {
+            Some(duration.num_milliseconds())
+        }
+    }
+    /// The time into the inner track at time, or None once all repetitions have played
+    pub fn local_time(&self, time: Duration) -> Option<Duration> {
+        if self.times == Some(0) {
+            return None;
+        }
+        let cycle_ms = match self.cycle_ms() {
+            Some(cycle_ms) => cycle_ms,
+            None => return Some(time)
+        };
+        let time_ms = time.num_milliseconds();
+        if time_ms < 0 {
+            return Some(time);
+        }
+        if let Some(n) = self.times {
+            let end_ms = n as i64 * cycle_ms;
+            if time_ms > end_ms {
+                return None;
+            } else if time_ms == end_ms && n > 0 {
+                return Some(Duration::milliseconds(cycle_ms));
+            }
+        }
+        Some(Duration::milliseconds(time_ms % cycle_ms))
+    }
+}
+
+impl Track for RepeatTrack {
+    fn value_at(&self, time: Duration) -> Vec<(NamedPropRef, Animatable)> {
+        match self.local_time(time) {
+            Some(time) => self.track.value_at(time),
+            None => vec![]
+        }
+    }
+    fn value_at_reusing(&self, time: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) {
+        match self.local_time(time) {
+            Some(time) => self.track.value_at_reusing(time, out),
+            None => out.clear()
+        }
+    }
+    fn seek_reusing(&self, from: Duration, to: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) -> Vec<Event> {
+        if self.cycle_ms().is_none() && self.times != Some(0) {
+            // Tracks that can't be repeated play as they are
+            return self.track.seek_reusing(from, to, out);
+        }
+        match (self.local_time(from), self.local_time(to)) {
+            (_, Some(local_to)) => self.track.value_at_reusing(local_to, out),
+            // Crossing the end of the last repetition, the inner track sees itself crossing its end
+            (Some(local_from), None) => { self.track.seek_reusing(local_from, local_from + (to - from), out); },
+            (None, None) => out.clear()
+        }
+        if from < to { self.events_between(from, to) } else { vec![] }
+    }
+    fn affected_properties(&self) -> Vec<NamedPropRef> {
+        self.track.affected_properties()
+    }
+    fn duration(&self) -> Duration {
+        match (self.cycle_ms(), self.times) {
+            (Some(cycle_ms), Some(n)) => Duration::milliseconds(cycle_ms * n as i64),
+            _ => infinite_duration()
+        }
+    }
+    fn is_finished(&self, time: Duration) -> bool {
+        match self.local_time(time) {
+            Some(local) => self.cycle_ms().is_none() && self.track.is_finished(local),
+            None => true
+        }
+    }
+    fn events_between(&self, from: Duration, to: Duration) -> Vec<Event> {
+        let cycle_ms = match self.cycle_ms() {
+            Some(cycle_ms) => cycle_ms,
+            None => return self.track.events_between(from, to)
+        };
+        let mut res = vec![];
+        let mut cycle = cmp::max(from.num_milliseconds(), 0) / cycle_ms;
+        while cycle * cycle_ms < to.num_milliseconds() {
+            if let Some(n) = self.times {
+                if cycle >= n as i64 { break; }
+            }
+            let start = Duration::milliseconds(cycle * cycle_ms);
+            let local_from = if from > start { from - start } else { Duration::zero() };
+            let local_to = to - start;
+            // The start of a cycle is the same instant as the end of the one before it, which already
+            // fired the events there
+            let skip_start = cycle > 0 && local_from == Duration::zero();
+            for event in self.track.events_between(local_from, local_to) {
+                if event.time.num_milliseconds() <= cycle_ms && !(skip_start && event.time == Duration::zero()) {
+                    res.push(event);
+                }
+            }
+            cycle += 1;
+        }
+        res
+    }
+}
+
+impl Translatable<RepeatTrack> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<RepeatTrack, PonTranslateErr> {
+        self.as_typed(|&TypedPon { ref type_name, ref data }| {
+            match type_name.as_str() {
+                "repeat" => {
+                    let times = match data.field("times") {
+                        Ok(times) => {
+                            let times: f32 = try!(times.translate::<f32>(context));
+                            if times < 1.0 || times.fract() != 0.0 {
+                                return Err(PonTranslateErr::InvalidValue { value: times.to_string() });
+                            }
+                            Some(times as u32)
+                        },
+                        Err(_) => None
+                    };
+                    Ok(RepeatTrack {
+                        track: try!(data.field_as::<Box<Track>>("track", context)),
+                        times: times
+                    })
+                },
+                s @ _ => Err(PonTranslateErr::UnrecognizedType(s.to_string()))
+            }
+        })
+    }
+}
+
+#[test]
+fn test_repeat() {
+    let repeated: RepeatTrack = Pon::from_string(
+        "repeat { track: track_set [ key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], loop: 'once' } ], times: 3 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(repeated.value_at(Duration::milliseconds(250)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.25))]);
+    assert_eq!(repeated.value_at(Duration::milliseconds(1250)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.25))]);
+    assert_eq!(repeated.value_at(Duration::milliseconds(2500)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.5))]);
+    assert_eq!(repeated.value_at(Duration::milliseconds(3000)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(1.0))]);
+    assert_eq!(repeated.value_at(Duration::milliseconds(3250)), vec![]);
+    assert_eq!(repeated.duration(), Duration::seconds(3));
+    assert!(!repeated.is_finished(Duration::milliseconds(2500)));
+    assert!(repeated.is_finished(Duration::milliseconds(3250)));
+}
+
+#[test]
+fn test_repeat_zero_times() {
+    let res: Result<RepeatTrack, PonTranslateErr> = Pon::from_string(
+        "repeat { track: key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]] }, times: 0 }")
+        .unwrap().translate(&mut TranslateContext::empty());
+    assert!(res.is_err());
+    let track: Box<Track> = Pon::from_string("key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let repeated = RepeatTrack { track: track, times: Some(0) };
+    assert_eq!(repeated.value_at(Duration::zero()), vec![]);
+    assert_eq!(repeated.duration(), Duration::zero());
+}
+
+#[test]
+fn test_repeat_events() {
+    let repeated: RepeatTrack = Pon::from_string(
+        "repeat { track: sequence [ events { events: [{ time: 0.5, name: 'step' }] }, key_framed { property: this.x, keys: [[0.0, 0.0], [0.5, 1.0]], duration: 0.5 } ], times: 2 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(repeated.events_between(Duration::zero(), Duration::seconds(5)).len(), 2);
+    assert_eq!(repeated.events_between(Duration::milliseconds(1400), Duration::milliseconds(1600)).len(), 1);
+}
+
+#[test]
+fn test_repeat_event_track() {
+    let repeated: RepeatTrack = Pon::from_string(
+        "repeat { track: events { events: [{ time: 0.5, name: 'step' }] }, times: 3 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(repeated.events_between(Duration::zero(), Duration::seconds(5)).len(), 3);
+    let frames: usize = (0..20).map(|i| repeated.events_between(Duration::milliseconds(i * 100), Duration::milliseconds((i + 1) * 100)).len()).fold(0, |sum, n| sum + n);
+    assert_eq!(frames, 3);
+    let repeated: RepeatTrack = Pon::from_string(
+        "repeat { track: events { events: [{ time: 0.0, name: 'start' }, { time: 1.0, name: 'end' }] }, times: 2 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let names: Vec<String> = repeated.events_between(Duration::zero(), Duration::seconds(5)).into_iter().map(|e| e.name).collect();
+    assert_eq!(names, vec!["start", "end", "end"]);
+}
@@ -0,0 +1,131 @@
+
+use std::collections::HashMap;
+use time::*;
+use track::*;
+use pyramid::pon::*;
+use animatable::*;
+use event_track::*;
+
+/// Rewrites which properties a track writes to.
+#[derive(Debug)]
+pub struct RetargetTrack {
+    pub track: Box<Track>,
+    pub mapping: HashMap<NamedPropRef, NamedPropRef>,
+    /// Drop properties that aren't in the mapping, instead of passing them through
+    pub strict: bool
+}
+
+impl RetargetTrack {
+    fn retarget(&self, values: Vec<(NamedPropRef, Animatable)>) -> Vec<(NamedPropRef, Animatable)> {
+        let mut res = vec![];
+        for (prop, value) in values {
+            match self.mapping.get(&prop) {
+                Some(target) => res.push((target.clone(), value)),
+                None if !self.strict => res.push((prop, value)),
+                None => {}
+            }
+        }
+        res
+    }
+}
+
+impl Track for RetargetTrack {
+    fn value_at(&self, time: Duration) -> Vec<(NamedPropRef, Animatable)> {
+        self.retarget(self.track.value_at(time))
+    }
+    // The values get new property refs, so the inner track has nothing to reuse
+    fn seek_reusing(&self, from: Duration, to: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) -> Vec<Event> {
+        let seek = self.track.seek(from, to);
+        *out = self.retarget(seek.values);
+        seek.events
+    }
+    fn affected_properties(&self) -> Vec<NamedPropRef> {
+        let mut res = vec![];
+        for prop in self.track.affected_properties() {
+            match self.mapping.get(&prop) {
+                Some(target) => add_properties(&mut res, vec![target.clone()]),
+                None if !self.strict => add_properties(&mut res, vec![prop]),
+                None => {}
+            }
+        }
+        res
+    }
+    fn duration(&self) -> Duration {
+        self.track.duration()
+    }
+    fn is_finished(&self, time: Duration) -> bool {
+        self.track.is_finished(time)
+    }
+    fn events_between(&self, from: Duration, to: Duration) -> Vec<Event> {
+        self.track.events_between(from, to)
+    }
+}
+
+impl Translatable<RetargetTrack> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<RetargetTrack, PonTranslateErr> {
+        self.as_typed(|&TypedPon { ref type_name, ref data }| {
+            match type_name.as_str() {
+                "retarget" => {
+                    let mut mapping = HashMap::new();
+                    match try!(data.field("map")) {
+                        &Pon::Array(ref pairs) => {
+                            for pair in pairs {
+                                match pair {
+                                    &Pon::Array(ref pair) if pair.len() == 2 => {
+                                        let from: &NamedPropRef = try!(pair[0].as_reference());
+                                        let to: &NamedPropRef = try!(pair[1].as_reference());
+                                        mapping.insert(from.clone(), to.clone());
+                                    },
+                                    _ => return Err(PonTranslateErr::MismatchType { expected: "[from, to]".to_string(), found: format!("{:?}", pair) })
+                                }
+                            }
+                        },
+                        map @ _ => return Err(PonTranslateErr::MismatchType { expected: "Array".to_string(), found: format!("{:?}", map) })
+                    }
+                    Ok(RetargetTrack {
+                        track: try!(data.field_as::<Box<Track>>("track", context)),
+                        mapping: mapping,
+                        strict: try!(data.field_as_or("strict", false, context))
+                    })
+                },
+                s @ _ => Err(PonTranslateErr::UnrecognizedType(s.to_string()))
+            }
+        })
+    }
+}
+
+#[test]
+fn test_retarget() {
+    let retargeted: RetargetTrack = Pon::from_string(
+        "retarget { track: track_set [ fixed_value { property: this.x, value: 0.5 }, fixed_value { property: this.y, value: 0.2 } ],
+            map: [[this.x, child.position_x]] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let target = Pon::from_string("child.position_x").unwrap();
+    let target: &NamedPropRef = target.as_reference().unwrap();
+    assert_eq!(retargeted.value_at(Duration::zero()), vec![
+        (target.clone(), Animatable::new_float(0.5)),
+        (NamedPropRef::new(EntityPath::This, "y"), Animatable::new_float(0.2))
+    ]);
+}
+
+#[test]
+fn test_retarget_strict() {
+    let retargeted: RetargetTrack = Pon::from_string(
+        "retarget { track: track_set [ fixed_value { property: this.x, value: 0.5 }, fixed_value { property: this.y, value: 0.2 } ],
+            map: [[this.x, this.z]], strict: true }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(retargeted.value_at(Duration::zero()), vec![
+        (NamedPropRef::new(EntityPath::This, "z"), Animatable::new_float(0.5))
+    ]);
+}
+
+#[test]
+fn test_retarget_affected_properties() {
+    let mut retargeted: RetargetTrack = Pon::from_string(
+        "retarget { track: track_set [ fixed_value { property: this.x, value: 0.5 }, fixed_value { property: this.y, value: 0.2 } ],
+            map: [[this.x, this.z]] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(retargeted.affected_properties(), this_props(&["z", "y"]));
+    retargeted.strict = true;
+    assert_eq!(retargeted.affected_properties(), this_props(&["z"]));
+}
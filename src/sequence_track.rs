@@ -0,0 +1,181 @@
+
+use std::cmp;
+use time::*;
+use track::*;
+use pyramid::pon::*;
+use animatable::*;
+use event_track::*;
+
+/// Plays each track after the other, starting each one when the previous one's duration has passed.
+#[derive(Debug)]
+pub struct SequenceTrack {
+    pub tracks: Vec<Box<Track>>
+}
+
+impl SequenceTrack {
+    /// Start time of each track, and its end time unless it plays forever
+    fn windows(&self) -> Vec<(Duration, Option<Duration>)> {
+        let mut res = vec![];
+        let mut start = Some(Duration::zero());
+        for track in &self.tracks {
+            let s = match start {
+                Some(s) => s,
+                None => break
+            };
+            let duration = track.duration();
+            let end = if duration == infinite_duration() { None } else { s.checked_add(&duration) };
+            res.push((s, end));
+            start = end;
+        }
+        res
+    }
+    /// Index of the track that is playing at time, and its start time
+    fn active(&self, time: Duration) -> Option<(usize, Duration)> {
+        let windows = self.windows();
+        for (i, &(start, end)) in windows.iter().enumerate() {
+            match end {
+                Some(end) if time >= end && i + 1 < windows.len() => continue,
+                _ => return Some((i, start))
+            }
+        }
+        None
+    }
+}
+
+impl Track for SequenceTrack {
+    fn value_at(&self, time: Duration) -> Vec<(NamedPropRef, Animatable)> {
+        match self.active(time) {
+            Some((i, start)) => self.tracks[i].value_at(time - start),
+            None => vec![]
+        }
+    }
+    fn value_at_into(&self, time: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) {
+        if let Some((i, start)) = self.active(time) {
+            self.tracks[i].value_at_into(time - start, out);
+        }
+    }
+    fn value_at_reusing(&self, time: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) {
+        match self.active(time) {
+            Some((i, start)) => self.tracks[i].value_at_reusing(time - start, out),
+            None => out.clear()
+        }
+    }
+    fn seek_reusing(&self, from: Duration, to: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) -> Vec<Event> {
+        // The track playing at to is seeked from its start if from was before it, the events
+        // come from every track played in between
+        match self.active(to) {
+            Some((i, start)) => { self.tracks[i].seek_reusing(cmp::max(from, start) - start, to - start, out); },
+            None => out.clear()
+        }
+        if from < to { self.events_between(from, to) } else { vec![] }
+    }
+    fn affected_properties(&self) -> Vec<NamedPropRef> {
+        let mut res = vec![];
+        for track in &self.tracks {
+            add_properties(&mut res, track.affected_properties());
+        }
+        res
+    }
+    fn duration(&self) -> Duration {
+        match self.windows().last() {
+            Some(&(_, Some(end))) => end,
+            Some(&(_, None)) => infinite_duration(),
+            None => Duration::zero()
+        }
+    }
+    fn is_finished(&self, time: Duration) -> bool {
+        let windows = self.windows();
+        match windows.last() {
+            Some(&(start, _)) => time >= start && self.tracks[windows.len() - 1].is_finished(time - start),
+            None => true
+        }
+    }
+    fn events_between(&self, from: Duration, to: Duration) -> Vec<Event> {
+        let mut res = vec![];
+        // Tracks don't have events after their duration, so only the start needs clipping
+        for (i, &(start, _)) in self.windows().iter().enumerate() {
+            let local_from = cmp::max(from, start);
+            if local_from < to {
+                res.extend(self.tracks[i].events_between(local_from - start, to - start).into_iter());
+            }
+        }
+        res
+    }
+}
+
+impl Translatable<SequenceTrack> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<SequenceTrack, PonTranslateErr> {
+        self.as_typed(|&TypedPon { ref type_name, ref data }| {
+            match type_name.as_str() {
+                "sequence" => {
+                    let tracks = try!(data.translate::<PonAutoVec<Box<Track>>>(context));
+                    Ok(SequenceTrack {
+                        tracks: tracks.0
+                    })
+                },
+                s @ _ => Err(PonTranslateErr::UnrecognizedType(s.to_string()))
+            }
+        })
+    }
+}
+
+#[test]
+fn test_sequence() {
+    let sequence: SequenceTrack = Pon::from_string(
+        "sequence [
+            key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], duration: 1.0 },
+            key_framed { property: this.y, keys: [[0.0, 0.0], [2.0, 1.0]], duration: 2.0 }
+        ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(sequence.value_at(Duration::milliseconds(500)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.5))]);
+    assert_eq!(sequence.value_at(Duration::milliseconds(2000)), vec![(NamedPropRef::new(EntityPath::This, "y"), Animatable::new_float(0.5))]);
+    assert_eq!(sequence.value_at(Duration::milliseconds(3500)), vec![]);
+    assert_eq!(sequence.duration(), Duration::seconds(3));
+    assert!(sequence.is_finished(Duration::milliseconds(3500)));
+}
+
+#[test]
+fn test_sequence_events() {
+    let sequence: SequenceTrack = Pon::from_string(
+        "sequence [
+            events { events: [{ time: 0.5, name: 'a' }, { time: 1.0, name: 'b' }] },
+            key_framed { property: this.y, keys: [[0.0, 0.0], [1.0, 1.0]], duration: 1.0 },
+            events { events: [{ time: 0.0, name: 'c' }] }
+        ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let names: Vec<String> = sequence.events_between(Duration::zero(), Duration::seconds(3))
+        .into_iter().map(|e| e.name).collect();
+    assert_eq!(names, vec!["a".to_string(), "b".to_string(), "c".to_string()]);
+}
+
+#[test]
+fn test_sequence_value_at_into() {
+    let sequence: SequenceTrack = Pon::from_string(
+        "sequence [
+            key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], duration: 1.0 },
+            delay { track: fixed_value { property: this.y, value: 0.5 }, by: 0.5 }
+        ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let mut buffer = vec![];
+    for i in 0..40 {
+        let time = Duration::milliseconds(i * 100);
+        buffer.clear();
+        sequence.value_at_into(time, &mut buffer);
+        assert_eq!(buffer, sequence.value_at(time));
+    }
+    // Appends rather than replacing what's already there
+    sequence.value_at_into(Duration::milliseconds(500), &mut buffer);
+    assert_eq!(buffer.len(), 2);
+}
+
+#[test]
+fn test_sequence_affected_properties() {
+    let sequence: SequenceTrack = Pon::from_string(
+        "sequence [
+            key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], duration: 1.0 },
+            key_framed { property: this.y, keys: [[0.0, 0.0], [1.0, 1.0]], duration: 1.0 },
+            key_framed { property: this.x, keys: [[0.0, 1.0], [1.0, 0.0]], duration: 1.0 }
+        ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(sequence.affected_properties(), this_props(&["x", "y"]));
+}
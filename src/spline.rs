@@ -0,0 +1,540 @@
+
+use std::cmp::Ordering;
+use curve::*;
+use pyramid::pon::*;
+
+/// Evaluates a cubic hermite segment, with tangents scaled to the 0 to 1 segment parameter s.
+pub fn hermite(p0: f32, m0: f32, p1: f32, m1: f32, s: f32) -> f32 {
+    let s2 = s * s;
+    let s3 = s2 * s;
+    (2.0 * s3 - 3.0 * s2 + 1.0) * p0 + (s3 - 2.0 * s2 + s) * m0 + (-2.0 * s3 + 3.0 * s2) * p1 + (s3 - s2) * m1
+}
+
+/// Index of the first key of the segment containing time, or None if time is outside of the keys.
+/// The keys are sorted by time, so this is a binary search.
+fn segment_index<K, F: Fn(&K) -> f32>(keys: &[K], time: f32, key_time: F) -> Option<usize> {
+    if keys.len() < 2 || time < key_time(&keys[0]) || time >= key_time(&keys[keys.len() - 1]) {
+        return None;
+    }
+    // The comparison never returns Equal, so the search always ends with the number of keys at or before time
+    let after = match keys.binary_search_by(|key| if key_time(key) <= time { Ordering::Less } else { Ordering::Greater }) {
+        Ok(i) => i,
+        Err(i) => i
+    };
+    Some(after - 1)
+}
+
+/// Where a curve's slope jumps at a key, see the validate methods of the spline curves
+#[derive(PartialEq, Debug, Clone)]
+pub struct Discontinuity {
+    pub time: f32,
+    /// Slope just before the key
+    pub incoming: f32,
+    /// Slope just after the key
+    pub outgoing: f32
+}
+
+/// Finds the key where the slope of the curve jumps the most, if that is more than tolerance.
+/// The slopes are estimated from samples on either side of each key between the first and last,
+/// so this is meant for debugging keys and handles, not for calling while sampling.
+fn check_continuity(curve: &Curve<f32>, times: &[f32], tolerance: f32) -> Result<(), Discontinuity> {
+    let mut worst: Option<Discontinuity> = None;
+    for i in 1..times.len().saturating_sub(1) {
+        let t = times[i];
+        let h = (t - times[i - 1]).min(times[i + 1] - t) * 0.001;
+        // Second order one sided differences, so the curvature doesn't show up as a jump
+        let incoming = (3.0 * curve.value(t) - 4.0 * curve.value(t - h) + curve.value(t - 2.0 * h)) / (2.0 * h);
+        let outgoing = (-3.0 * curve.value(t) + 4.0 * curve.value(t + h) - curve.value(t + 2.0 * h)) / (2.0 * h);
+        let jump = (outgoing - incoming).abs();
+        let is_worse = match worst {
+            Some(ref w) => jump > (w.outgoing - w.incoming).abs(),
+            None => jump > tolerance
+        };
+        if is_worse {
+            worst = Some(Discontinuity { time: t, incoming: incoming, outgoing: outgoing });
+        }
+    }
+    match worst {
+        Some(discontinuity) => Err(discontinuity),
+        None => Ok(())
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct TcbKey {
+    pub time: f32,
+    pub value: f32,
+    pub tension: f32,
+    pub continuity: f32,
+    pub bias: f32
+}
+
+impl TcbKey {
+    pub fn new(time: f32, value: f32) -> TcbKey {
+        TcbKey {
+            time: time,
+            value: value,
+            tension: 0.0,
+            continuity: 0.0,
+            bias: 0.0
+        }
+    }
+}
+
+/// Kochanek-Bartels spline. With all parameters at zero and evenly spaced keys this
+/// is a Catmull-Rom spline.
+#[derive(PartialEq, Debug)]
+pub struct TcbCurve {
+    pub keys: Vec<TcbKey>
+}
+
+impl TcbCurve {
+    /// Incoming and outgoing tangents of key i, as slopes per unit of time
+    pub fn tangents(&self, i: usize) -> (f32, f32) {
+        let key = &self.keys[i];
+        let slope = |a: &TcbKey, b: &TcbKey| (b.value - a.value) / (b.time - a.time);
+        let prev = if i > 0 { Some(slope(&self.keys[i - 1], key)) } else { None };
+        let next = if i + 1 < self.keys.len() { Some(slope(key, &self.keys[i + 1])) } else { None };
+        let (prev, next) = match (prev, next) {
+            (Some(p), Some(n)) => (p, n),
+            (Some(p), None) => (p, p),
+            (None, Some(n)) => (n, n),
+            (None, None) => (0.0, 0.0)
+        };
+        let (t, c, b) = (key.tension, key.continuity, key.bias);
+        let incoming = (1.0 - t) * (1.0 + b) * (1.0 + c) / 2.0 * prev + (1.0 - t) * (1.0 - b) * (1.0 - c) / 2.0 * next;
+        let outgoing = (1.0 - t) * (1.0 + b) * (1.0 - c) / 2.0 * prev + (1.0 - t) * (1.0 - b) * (1.0 + c) / 2.0 * next;
+        (incoming, outgoing)
+    }
+    /// Checks that the slope doesn't jump by more than tolerance at any key, which a non zero
+    /// continuity does on purpose. Fails with the key where it jumps the most.
+    pub fn validate(&self, tolerance: f32) -> Result<(), Discontinuity> {
+        let times: Vec<f32> = self.keys.iter().map(|k| k.time).collect();
+        check_continuity(self, &times, tolerance)
+    }
+}
+
+impl Curve<f32> for TcbCurve {
+    fn value(&self, time: f32) -> f32 {
+        if self.keys.is_empty() {
+            return 0.0;
+        }
+        match segment_index(&self.keys, time, |k| k.time) {
+            Some(i) => {
+                let (a, b) = (&self.keys[i], &self.keys[i + 1]);
+                let d = b.time - a.time;
+                let m0 = self.tangents(i).1 * d;
+                let m1 = self.tangents(i + 1).0 * d;
+                hermite(a.value, m0, b.value, m1, (time - a.time) / d)
+            },
+            None if time < self.keys[0].time => self.keys[0].value,
+            None => self.keys[self.keys.len() - 1].value
+        }
+    }
+    fn domain(&self) -> (f32, f32) {
+        match (self.keys.first(), self.keys.last()) {
+            (Some(first), Some(last)) => (first.time, last.time),
+            _ => (0.0, 0.0)
+        }
+    }
+}
+
+/// Cubic interpolation that preserves monotonicity of the keys (Fritsch-Carlson), so it
+/// never overshoots between keys that are monotone.
+#[derive(PartialEq, Debug)]
+pub struct MonotoneCubicCurve {
+    keys: Vec<Key<f32>>,
+    tangents: Vec<f32>
+}
+
+impl MonotoneCubicCurve {
+    pub fn new(keys: Vec<Key<f32>>) -> MonotoneCubicCurve {
+        let n = keys.len();
+        let deltas: Vec<f32> = (0..n.saturating_sub(1))
+            .map(|k| (keys[k + 1].1 - keys[k].1) / (keys[k + 1].0 - keys[k].0))
+            .collect();
+        let mut tangents = vec![0.0; n];
+        if n > 1 {
+            tangents[0] = deltas[0];
+            tangents[n - 1] = deltas[n - 2];
+            for k in 1..n - 1 {
+                tangents[k] = if deltas[k - 1] * deltas[k] <= 0.0 { 0.0 } else { (deltas[k - 1] + deltas[k]) / 2.0 };
+            }
+            for k in 0..n - 1 {
+                if deltas[k] == 0.0 {
+                    tangents[k] = 0.0;
+                    tangents[k + 1] = 0.0;
+                    continue;
+                }
+                let a = tangents[k] / deltas[k];
+                let b = tangents[k + 1] / deltas[k];
+                if a < 0.0 { tangents[k] = 0.0; }
+                if b < 0.0 { tangents[k + 1] = 0.0; }
+                let s = a * a + b * b;
+                if s > 9.0 {
+                    let tau = 3.0 / s.sqrt();
+                    tangents[k] = tau * a * deltas[k];
+                    tangents[k + 1] = tau * b * deltas[k];
+                }
+            }
+        }
+        MonotoneCubicCurve {
+            keys: keys,
+            tangents: tangents
+        }
+    }
+    pub fn keys(&self) -> &Vec<Key<f32>> {
+        &self.keys
+    }
+    /// Checks that the slope doesn't jump by more than tolerance at any key,
+    /// failing with the key where it jumps the most
+    pub fn validate(&self, tolerance: f32) -> Result<(), Discontinuity> {
+        let times: Vec<f32> = self.keys.iter().map(|k| k.0).collect();
+        check_continuity(self, &times, tolerance)
+    }
+}
+
+impl Curve<f32> for MonotoneCubicCurve {
+    fn value(&self, time: f32) -> f32 {
+        if self.keys.is_empty() {
+            return 0.0;
+        }
+        match segment_index(&self.keys, time, |k| k.0) {
+            Some(i) => {
+                let (a, b) = (&self.keys[i], &self.keys[i + 1]);
+                let d = b.0 - a.0;
+                hermite(a.1, self.tangents[i] * d, b.1, self.tangents[i + 1] * d, (time - a.0) / d)
+            },
+            None if time < self.keys[0].0 => self.keys[0].1,
+            None => self.keys[self.keys.len() - 1].1
+        }
+    }
+    fn domain(&self) -> (f32, f32) {
+        match (self.keys.first(), self.keys.last()) {
+            (Some(first), Some(last)) => (first.0, last.0),
+            _ => (0.0, 0.0)
+        }
+    }
+}
+
+/// Akima spline, which weighs neighbouring slopes so that a sudden change in the keys
+/// only affects the curve close to it.
+#[derive(PartialEq, Debug)]
+pub struct AkimaCurve {
+    keys: Vec<Key<f32>>,
+    tangents: Vec<f32>
+}
+
+impl AkimaCurve {
+    pub fn new(keys: Vec<Key<f32>>) -> AkimaCurve {
+        let n = keys.len();
+        let mut tangents = vec![0.0; n];
+        if n > 1 {
+            // Segment slopes, padded with two extrapolated slopes at each end
+            let mut m = vec![0.0; n + 3];
+            for k in 0..n - 1 {
+                m[k + 2] = (keys[k + 1].1 - keys[k].1) / (keys[k + 1].0 - keys[k].0);
+            }
+            if n > 2 {
+                m[1] = 2.0 * m[2] - m[3];
+                m[0] = 2.0 * m[1] - m[2];
+                m[n + 1] = 2.0 * m[n] - m[n - 1];
+                m[n + 2] = 2.0 * m[n + 1] - m[n];
+            } else {
+                m[0] = m[2];
+                m[1] = m[2];
+                m[3] = m[2];
+                m[4] = m[2];
+            }
+            for i in 0..n {
+                let w1 = (m[i + 3] - m[i + 2]).abs();
+                let w2 = (m[i + 1] - m[i]).abs();
+                tangents[i] = if w1 + w2 == 0.0 {
+                    (m[i + 1] + m[i + 2]) / 2.0
+                } else {
+                    (w1 * m[i + 1] + w2 * m[i + 2]) / (w1 + w2)
+                };
+            }
+        }
+        AkimaCurve {
+            keys: keys,
+            tangents: tangents
+        }
+    }
+    pub fn keys(&self) -> &Vec<Key<f32>> {
+        &self.keys
+    }
+    /// Checks that the slope doesn't jump by more than tolerance at any key,
+    /// failing with the key where it jumps the most
+    pub fn validate(&self, tolerance: f32) -> Result<(), Discontinuity> {
+        let times: Vec<f32> = self.keys.iter().map(|k| k.0).collect();
+        check_continuity(self, &times, tolerance)
+    }
+}
+
+impl Curve<f32> for AkimaCurve {
+    fn value(&self, time: f32) -> f32 {
+        if self.keys.is_empty() {
+            return 0.0;
+        }
+        match segment_index(&self.keys, time, |k| k.0) {
+            Some(i) => {
+                let (a, b) = (&self.keys[i], &self.keys[i + 1]);
+                let d = b.0 - a.0;
+                hermite(a.1, self.tangents[i] * d, b.1, self.tangents[i + 1] * d, (time - a.0) / d)
+            },
+            None if time < self.keys[0].0 => self.keys[0].1,
+            None => self.keys[self.keys.len() - 1].1
+        }
+    }
+    fn domain(&self) -> (f32, f32) {
+        match (self.keys.first(), self.keys.last()) {
+            (Some(first), Some(last)) => (first.0, last.0),
+            _ => (0.0, 0.0)
+        }
+    }
+}
+
+#[cfg(test)]
+fn assert_near(a: f32, b: f32) {
+    assert!((a - b).abs() < 0.0001, "{} != {}", a, b);
+}
+
+#[test]
+fn test_tcb_passes_through_keys() {
+    let curve = TcbCurve {
+        keys: vec![TcbKey::new(0.0, 0.0), TcbKey::new(1.0, 2.0), TcbKey::new(2.5, -1.0), TcbKey::new(3.0, 4.0)]
+    };
+    for key in &curve.keys {
+        assert_near(curve.value(key.time), key.value);
+    }
+}
+
+#[test]
+fn test_tcb_default_is_catmull_rom() {
+    let curve = TcbCurve {
+        keys: vec![TcbKey::new(0.0, 0.0), TcbKey::new(1.0, 1.0), TcbKey::new(2.0, 3.0), TcbKey::new(3.0, 4.0)]
+    };
+    assert_near(curve.value(1.5), 2.0);
+}
+
+#[test]
+fn test_tcb_full_tension_flattens_tangents() {
+    let mut keys = vec![TcbKey::new(0.0, 0.0), TcbKey::new(1.0, 1.0), TcbKey::new(2.0, 3.0)];
+    for key in keys.iter_mut() {
+        key.tension = 1.0;
+    }
+    let curve = TcbCurve { keys: keys };
+    for i in 0..3 {
+        assert_eq!(curve.tangents(i), (0.0, 0.0));
+    }
+    assert_near(curve.value(1.5), 2.0);
+    assert_near(curve.value(1.0), 1.0);
+}
+
+#[test]
+fn test_monotone_cubic_no_overshoot() {
+    let keys = vec![Key(0.0, 0.0), Key(1.0, 0.0), Key(2.0, 1.0), Key(3.0, 1.0), Key(3.5, 5.0)];
+    let curve = MonotoneCubicCurve::new(keys.clone());
+    let mut last = curve.value(0.0);
+    for i in 0..351 {
+        let t = i as f32 * 0.01;
+        let v = curve.value(t);
+        let seg = bracketing_segment(&keys, t);
+        assert!(v >= keys[seg].1 - 0.00001 && v <= keys[seg + 1].1 + 0.00001, "{} out of range at {}", v, t);
+        assert!(v >= last - 0.00001);
+        last = v;
+    }
+    for key in &keys {
+        assert_near(curve.value(key.0), key.1);
+    }
+    // Catmull-Rom undershoots on the same data
+    let catmull_rom = TcbCurve { keys: keys.iter().map(|k| TcbKey::new(k.0, k.1)).collect() };
+    assert!(catmull_rom.value(0.5) < 0.0);
+}
+
+#[cfg(test)]
+fn bracketing_segment(keys: &Vec<Key<f32>>, t: f32) -> usize {
+    segment_index(keys, t, |k| k.0).unwrap_or(keys.len() - 2)
+}
+
+#[test]
+fn test_akima_spike_overshoot() {
+    let keys = vec![Key(0.0, 0.0), Key(1.0, 0.0), Key(2.0, 0.0), Key(3.0, 5.0), Key(4.0, 0.0), Key(5.0, 0.0), Key(6.0, 0.0)];
+    let akima = AkimaCurve::new(keys.clone());
+    let catmull_rom = TcbCurve { keys: keys.iter().map(|k| TcbKey::new(k.0, k.1)).collect() };
+    for key in &keys {
+        assert_near(akima.value(key.0), key.1);
+    }
+    let mut akima_overshoot = 0.0f32;
+    let mut catmull_rom_overshoot = 0.0f32;
+    for i in 0..61 {
+        let t = i as f32 * 0.1;
+        akima_overshoot = akima_overshoot.max(-akima.value(t));
+        catmull_rom_overshoot = catmull_rom_overshoot.max(-catmull_rom.value(t));
+    }
+    assert!(catmull_rom_overshoot > 0.1);
+    assert!(akima_overshoot < catmull_rom_overshoot);
+    assert_near(akima.value(1.5), 0.0);
+}
+
+#[test]
+fn test_validate_kinked_hermite() {
+    let keys = vec![TcbKey::new(0.0, 0.0), TcbKey::new(1.0, 1.0), TcbKey::new(2.0, 3.0), TcbKey::new(3.0, 4.0)];
+    let smooth = TcbCurve { keys: keys.clone() };
+    assert_eq!(smooth.validate(0.01), Ok(()));
+    let mut kinked = TcbCurve { keys: keys };
+    kinked.keys[2].continuity = 0.5;
+    let discontinuity = kinked.validate(0.01).unwrap_err();
+    assert_eq!(discontinuity.time, 2.0);
+    let (incoming, outgoing) = kinked.tangents(2);
+    assert!((discontinuity.incoming - incoming).abs() < 0.01, "{:?}", discontinuity);
+    assert!((discontinuity.outgoing - outgoing).abs() < 0.01, "{:?}", discontinuity);
+    assert_eq!(AkimaCurve::new(vec![Key(0.0, 0.0), Key(1.0, 2.0), Key(3.0, 1.0)]).validate(0.01), Ok(()));
+}
+
+#[test]
+fn test_spline_empty_keys() {
+    assert_eq!(TcbCurve { keys: vec![] }.value(0.5), 0.0);
+    assert_eq!(MonotoneCubicCurve::new(vec![]).value(0.5), 0.0);
+    assert_eq!(AkimaCurve::new(vec![]).value(0.5), 0.0);
+    assert_eq!(CubicBezierCurve { keys: vec![] }.value(0.5), 0.0);
+}
+
+#[test]
+fn test_spline_domains() {
+    let keys = vec![Key(0.5, 0.0), Key(1.0, 2.0), Key(3.0, 1.0)];
+    assert_eq!(TcbCurve { keys: keys.iter().map(|k| TcbKey::new(k.0, k.1)).collect() }.domain(), (0.5, 3.0));
+    assert_eq!(MonotoneCubicCurve::new(keys.clone()).domain(), (0.5, 3.0));
+    assert_eq!(AkimaCurve::new(keys).domain(), (0.5, 3.0));
+}
+
+/// A key with bezier handles, given as [time, value] offsets from the key
+#[derive(PartialEq, Debug, Clone)]
+pub struct BezierKey {
+    pub time: f32,
+    pub value: f32,
+    pub in_handle: (f32, f32),
+    pub out_handle: (f32, f32)
+}
+
+/// Cubic bezier segments between keys, like the curve editors of most animation tools.
+/// Handle times are clamped into their segment so the curve stays a function of time.
+#[derive(PartialEq, Debug)]
+pub struct CubicBezierCurve {
+    pub keys: Vec<BezierKey>
+}
+
+impl CubicBezierCurve {
+    fn bezier(p0: f32, p1: f32, p2: f32, p3: f32, s: f32) -> f32 {
+        let r = 1.0 - s;
+        r * r * r * p0 + 3.0 * r * r * s * p1 + 3.0 * r * s * s * p2 + s * s * s * p3
+    }
+    /// Checks that the slope doesn't jump by more than tolerance at any key, which happens when
+    /// a key's handles don't point in opposite directions. Fails with the key where it jumps the most.
+    pub fn validate(&self, tolerance: f32) -> Result<(), Discontinuity> {
+        let times: Vec<f32> = self.keys.iter().map(|k| k.time).collect();
+        check_continuity(self, &times, tolerance)
+    }
+}
+
+impl Curve<f32> for CubicBezierCurve {
+    fn value(&self, time: f32) -> f32 {
+        if self.keys.is_empty() {
+            return 0.0;
+        }
+        match segment_index(&self.keys, time, |k| k.time) {
+            Some(i) => {
+                let (a, b) = (&self.keys[i], &self.keys[i + 1]);
+                let t1 = (a.time + a.out_handle.0).max(a.time).min(b.time);
+                let t2 = (b.time + b.in_handle.0).max(a.time).min(b.time);
+                // Time is monotone in s, so bisect for the s that gives time
+                let (mut lo, mut hi) = (0.0, 1.0);
+                for _ in 0..30 {
+                    let mid = (lo + hi) / 2.0;
+                    if CubicBezierCurve::bezier(a.time, t1, t2, b.time, mid) < time {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                    }
+                }
+                let s = (lo + hi) / 2.0;
+                CubicBezierCurve::bezier(a.value, a.value + a.out_handle.1, b.value + b.in_handle.1, b.value, s)
+            },
+            None if time < self.keys[0].time => self.keys[0].value,
+            None => self.keys[self.keys.len() - 1].value
+        }
+    }
+    fn domain(&self) -> (f32, f32) {
+        match (self.keys.first(), self.keys.last()) {
+            (Some(first), Some(last)) => (first.time, last.time),
+            _ => (0.0, 0.0)
+        }
+    }
+}
+
+fn translate_handle(pon: &Pon, name: &str, context: &mut TranslateContext) -> Result<(f32, f32), PonTranslateErr> {
+    match pon.field(name) {
+        Ok(handle) => {
+            let handle = try!(handle.translate::<Vec<f32>>(context));
+            if handle.len() != 2 {
+                return Err(PonTranslateErr::InvalidValue { value: format!("{:?}", handle) });
+            }
+            Ok((handle[0], handle[1]))
+        },
+        Err(_) => Ok((0.0, 0.0))
+    }
+}
+
+impl Translatable<BezierKey> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<BezierKey, PonTranslateErr> {
+        Ok(BezierKey {
+            time: try!(self.field_as::<f32>("time", context)),
+            value: try!(self.field_as::<f32>("value", context)),
+            in_handle: try!(translate_handle(self, "in_handle", context)),
+            out_handle: try!(translate_handle(self, "out_handle", context))
+        })
+    }
+}
+
+/// True if any of the keys is an object with a bezier handle
+pub fn has_bezier_handles(keys: &Pon) -> bool {
+    match keys {
+        &Pon::Array(ref keys) => keys.iter().any(|key| key.field("in_handle").is_ok() || key.field("out_handle").is_ok()),
+        _ => false
+    }
+}
+
+#[test]
+fn test_bezier_from_pon() {
+    let curve: Box<Curve<f32>> = Pon::from_string(
+        "key_framed { keys: [
+            { time: 0.0, value: 0.0, out_handle: [0.25, 0.0] },
+            { time: 1.0, value: 1.0, in_handle: [-0.25, 0.0] }
+        ] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_near(curve.value(0.5), 0.5);
+    assert_near(curve.value(0.0), 0.0);
+    assert_near(curve.value(1.0), 1.0);
+    // Flat handles ease in and out
+    assert!(curve.value(0.1) < 0.1);
+    assert!(curve.value(0.9) > 0.9);
+}
+
+#[test]
+fn test_bezier_from_pon_unsorted() {
+    let curve: Box<Curve<f32>> = Pon::from_string(
+        "key_framed { keys: [
+            { time: 2.0, value: 0.0, in_handle: [-0.25, 0.0] },
+            { time: 0.0, value: 0.0, out_handle: [0.25, 0.0] },
+            { time: 1.0, value: 1.0, in_handle: [-0.25, 0.0], out_handle: [0.25, 0.0] }
+        ] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_near(curve.value(0.5), 0.5);
+    assert_near(curve.value(1.0), 1.0);
+    assert_near(curve.value(1.5), 0.5);
+    let duplicate: Result<Box<Curve<f32>>, PonTranslateErr> = Pon::from_string(
+        "key_framed { keys: [{ time: 0.0, value: 0.0, out_handle: [0.25, 0.0] }, { time: 0.0, value: 1.0 }] }")
+        .unwrap().translate(&mut TranslateContext::empty());
+    assert!(duplicate.is_err());
+}
@@ -0,0 +1,95 @@
+
+use time::*;
+use track::*;
+use pyramid::pon::*;
+use animatable::*;
+use event_track::*;
+
+/// Writes each component of the values a track produces to its own property,
+/// so a three component curve can drive this.x, this.y and this.z.
+/// Components without a property are dropped.
+#[derive(Debug)]
+pub struct SplitTrack {
+    pub track: Box<Track>,
+    pub properties: Vec<NamedPropRef>
+}
+
+impl SplitTrack {
+    fn split(&self, values: Vec<(NamedPropRef, Animatable)>) -> Vec<(NamedPropRef, Animatable)> {
+        let mut res = vec![];
+        for (_, value) in values {
+            for (prop, &x) in self.properties.iter().zip(value.value.iter()) {
+                res.push((prop.clone(), Animatable::new_float(x)));
+            }
+        }
+        res
+    }
+}
+
+impl Track for SplitTrack {
+    fn value_at(&self, time: Duration) -> Vec<(NamedPropRef, Animatable)> {
+        self.split(self.track.value_at(time))
+    }
+    // The values get new property refs, so the inner track has nothing to reuse
+    fn seek_reusing(&self, from: Duration, to: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) -> Vec<Event> {
+        let seek = self.track.seek(from, to);
+        *out = self.split(seek.values);
+        seek.events
+    }
+    fn affected_properties(&self) -> Vec<NamedPropRef> {
+        self.properties.clone()
+    }
+    fn duration(&self) -> Duration {
+        self.track.duration()
+    }
+    fn is_finished(&self, time: Duration) -> bool {
+        self.track.is_finished(time)
+    }
+    fn events_between(&self, from: Duration, to: Duration) -> Vec<Event> {
+        self.track.events_between(from, to)
+    }
+    fn is_constant_between(&self, from: Duration, to: Duration) -> bool {
+        self.track.is_constant_between(from, to)
+    }
+}
+
+impl Translatable<SplitTrack> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<SplitTrack, PonTranslateErr> {
+        self.as_typed(|&TypedPon { ref type_name, ref data }| {
+            match type_name.as_str() {
+                "split" => {
+                    let properties = match try!(data.field("property")) {
+                        &Pon::Array(ref arr) => {
+                            let mut properties = vec![];
+                            for prop in arr {
+                                let prop: &NamedPropRef = try!(prop.as_reference());
+                                properties.push(prop.clone());
+                            }
+                            properties
+                        },
+                        other @ _ => return Err(PonTranslateErr::MismatchType { expected: "Array".to_string(), found: format!("{:?}", other) })
+                    };
+                    Ok(SplitTrack {
+                        track: try!(data.field_as::<Box<Track>>("curve", context)),
+                        properties: properties
+                    })
+                },
+                s @ _ => Err(PonTranslateErr::UnrecognizedType(s.to_string()))
+            }
+        })
+    }
+}
+
+#[test]
+fn test_split() {
+    let split: SplitTrack = Pon::from_string(
+        "split { property: [this.x, this.y, this.z],
+            curve: key_framed { property: this.position, keys: [[0.0, [0.0, 0.0, 0.0]], [1.0, [2.0, 4.0, 6.0]]] } }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(split.value_at(Duration::milliseconds(500)), vec![
+        (NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(1.0)),
+        (NamedPropRef::new(EntityPath::This, "y"), Animatable::new_float(2.0)),
+        (NamedPropRef::new(EntityPath::This, "z"), Animatable::new_float(3.0))
+    ]);
+    assert_eq!(split.affected_properties(), this_props(&["x", "y", "z"]));
+}
@@ -0,0 +1,76 @@
+
+use time::*;
+use track::*;
+use pyramid::pon::*;
+use animatable::*;
+
+/// Steps through the frames of a sprite sheet at a fixed rate, looping forever.
+/// The value is the index of the current frame.
+#[derive(Debug)]
+pub struct SpriteTrack {
+    pub property: NamedPropRef,
+    pub frames: u32,
+    /// Frames per second
+    pub fps: f32
+}
+
+impl SpriteTrack {
+    pub fn frame_at(&self, time: Duration) -> u32 {
+        if self.frames == 0 || time < Duration::zero() {
+            return 0;
+        }
+        ((time.num_milliseconds() as f64 * self.fps as f64 / 1000.0) as u64 % self.frames as u64) as u32
+    }
+}
+
+impl Track for SpriteTrack {
+    fn value_at(&self, time: Duration) -> Vec<(NamedPropRef, Animatable)> {
+        vec![(self.property.clone(), Animatable::new_float(self.frame_at(time) as f32))]
+    }
+    fn affected_properties(&self) -> Vec<NamedPropRef> {
+        vec![self.property.clone()]
+    }
+    fn duration(&self) -> Duration {
+        infinite_duration()
+    }
+}
+
+impl Translatable<SpriteTrack> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<SpriteTrack, PonTranslateErr> {
+        self.as_typed(|&TypedPon { ref type_name, ref data }| {
+            match type_name.as_str() {
+                "sprite" => {
+                    let property: &NamedPropRef = try!(try!(data.field("property")).as_reference());
+                    let frames = try!(data.field_as::<f32>("frames", context));
+                    if frames < 1.0 || frames.fract() != 0.0 {
+                        return Err(PonTranslateErr::InvalidValue { value: frames.to_string() });
+                    }
+                    let fps = try!(data.field_as::<f32>("fps", context));
+                    if fps <= 0.0 {
+                        return Err(PonTranslateErr::InvalidValue { value: fps.to_string() });
+                    }
+                    Ok(SpriteTrack {
+                        property: property.clone(),
+                        frames: frames as u32,
+                        fps: fps
+                    })
+                },
+                s @ _ => Err(PonTranslateErr::UnrecognizedType(s.to_string()))
+            }
+        })
+    }
+}
+
+#[test]
+fn test_sprite_frames() {
+    let sprite: SpriteTrack = Pon::from_string("sprite { property: this.frame, frames: 8, fps: 12 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(sprite.value_at(Duration::zero()), vec![(NamedPropRef::new(EntityPath::This, "frame"), Animatable::new_float(0.0))]);
+    assert_eq!(sprite.frame_at(Duration::milliseconds(83)), 0);
+    assert_eq!(sprite.frame_at(Duration::milliseconds(84)), 1);
+    assert_eq!(sprite.frame_at(Duration::milliseconds(167)), 2);
+    assert_eq!(sprite.frame_at(Duration::milliseconds(666)), 7);
+    assert_eq!(sprite.frame_at(Duration::milliseconds(667)), 0);
+    assert_eq!(sprite.frame_at(Duration::milliseconds(750)), 1);
+    assert!(!sprite.is_finished(Duration::weeks(1)));
+}
@@ -0,0 +1,87 @@
+
+use time::*;
+use track::*;
+use curve::*;
+use pyramid::pon::*;
+use animatable::*;
+use event_track::*;
+
+/// Plays a track with its time driven by a curve, mapping real time in seconds
+/// to the track's time in seconds.
+#[derive(Debug)]
+pub struct TimeRemapTrack {
+    pub track: Box<Track>,
+    pub curve: Box<Curve<f32>>
+}
+
+fn to_seconds(time: Duration) -> f32 {
+    time.num_milliseconds() as f32 / 1000.0
+}
+
+fn from_seconds(seconds: f32) -> Duration {
+    Duration::milliseconds((seconds * 1000.0) as i64)
+}
+
+impl TimeRemapTrack {
+    pub fn local_time(&self, time: Duration) -> Duration {
+        from_seconds(self.curve.value(to_seconds(time)))
+    }
+}
+
+impl Track for TimeRemapTrack {
+    fn value_at(&self, time: Duration) -> Vec<(NamedPropRef, Animatable)> {
+        self.track.value_at(self.local_time(time))
+    }
+    fn value_at_reusing(&self, time: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) {
+        self.track.value_at_reusing(self.local_time(time), out)
+    }
+    fn seek_reusing(&self, from: Duration, to: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) -> Vec<Event> {
+        self.track.seek_reusing(self.local_time(from), self.local_time(to), out)
+    }
+    fn affected_properties(&self) -> Vec<NamedPropRef> {
+        self.track.affected_properties()
+    }
+    fn duration(&self) -> Duration {
+        from_seconds(self.curve.domain().1)
+    }
+    fn is_finished(&self, time: Duration) -> bool {
+        time > self.duration() && self.track.is_finished(self.local_time(time))
+    }
+    fn events_between(&self, from: Duration, to: Duration) -> Vec<Event> {
+        let (from, to) = (self.local_time(from), self.local_time(to));
+        if from < to {
+            self.track.events_between(from, to)
+        } else {
+            vec![]
+        }
+    }
+}
+
+impl Translatable<TimeRemapTrack> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<TimeRemapTrack, PonTranslateErr> {
+        self.as_typed(|&TypedPon { ref type_name, ref data }| {
+            match type_name.as_str() {
+                "time_remap" => {
+                    Ok(TimeRemapTrack {
+                        track: try!(data.field_as::<Box<Track>>("track", context)),
+                        curve: try!(data.field_as::<Box<Curve<f32>>>("curve", context))
+                    })
+                },
+                s @ _ => Err(PonTranslateErr::UnrecognizedType(s.to_string()))
+            }
+        })
+    }
+}
+
+#[test]
+fn test_time_remap_freeze() {
+    let remapped: TimeRemapTrack = Pon::from_string(
+        "time_remap { track: key_framed { property: this.x, keys: [[0.0, 0.0], [4.0, 1.0]], duration: 4.0 },
+            curve: key_framed { keys: [[0.0, 0.0], [1.0, 1.0], [2.0, 1.0], [3.0, 2.0]] } }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(remapped.value_at(Duration::milliseconds(500)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.125))]);
+    for &t in [1000, 1250, 1700, 2000].iter() {
+        assert_eq!(remapped.value_at(Duration::milliseconds(t)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.25))]);
+    }
+    assert_eq!(remapped.value_at(Duration::milliseconds(3000)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.5))]);
+}
@@ -4,23 +4,263 @@ use pyramid::pon::*;
 use curve_track::*;
 use track_set::*;
 use weighted_tracks::*;
+use event_track::*;
+use crossfade_track::*;
+use sequence_track::*;
+use delay_track::*;
+use repeat_track::*;
+use retarget_track::*;
+use time_remap_track::*;
+use invert_track::*;
+use clamp_track::*;
+use sprite_track::*;
+use split_track::*;
+use euler_rotation_track::*;
+use bool_track::*;
 use animatable::*;
 use std::fmt::Debug;
+#[cfg(not(feature = "sync"))]
 use std::rc::Rc;
+#[cfg(feature = "sync")]
+use std::sync::Arc;
+use std::collections::HashMap;
+use std::any::Any;
+use pyramid::document::*;
 
-pub trait Track : Debug {
+/// Bounds every track and curve has to meet. With the sync feature they have to be
+/// Send + Sync, so boxed tracks can be shared between threads.
+#[cfg(not(feature = "sync"))]
+pub trait ThreadSafety {}
+#[cfg(not(feature = "sync"))]
+impl<T: ?Sized> ThreadSafety for T {}
+#[cfg(feature = "sync")]
+pub trait ThreadSafety : Send + Sync {}
+#[cfg(feature = "sync")]
+impl<T: ?Sized + Send + Sync> ThreadSafety for T {}
+
+/// Shared ownership of resources like track sets, an Arc with the sync feature and an Rc otherwise
+#[cfg(not(feature = "sync"))]
+pub type Shared<T> = Rc<T>;
+#[cfg(feature = "sync")]
+pub type Shared<T> = Arc<T>;
+
+/// The duration of tracks that never end
+pub fn infinite_duration() -> Duration {
+    Duration::max_value()
+}
+
+/// Translates a time in seconds, or a string with a unit like '1500ms', '1.5s' or '2m'
+pub fn translate_duration(pon: &Pon, context: &mut TranslateContext) -> Result<Duration, PonTranslateErr> {
+    if let Ok(seconds) = pon.translate::<f32>(context) {
+        return Ok(Duration::milliseconds((seconds*1000.0) as i64));
+    }
+    let time = try!(pon.translate::<String>(context));
+    let (number, ms_per_unit) = if time.ends_with("ms") {
+        (&time[..time.len() - 2], 1.0)
+    } else if time.ends_with("s") {
+        (&time[..time.len() - 1], 1000.0)
+    } else if time.ends_with("m") {
+        (&time[..time.len() - 1], 60000.0)
+    } else {
+        return Err(PonTranslateErr::InvalidValue { value: time.clone() });
+    };
+    match number.trim().parse::<f32>() {
+        Ok(n) if n >= 0.0 => Ok(Duration::milliseconds((n*ms_per_unit) as i64)),
+        _ => Err(PonTranslateErr::InvalidValue { value: time.clone() })
+    }
+}
+
+/// Looks up the document resource id for the Pon type type_name, failing if there's no document,
+/// no such resource or the resource isn't a T, which is described as kind in the error
+pub fn find_resource<'a, T: Any>(context: &TranslateContext<'a>, type_name: &str, id: &str, kind: &str) -> Result<&'a T, PonTranslateErr> {
+    let document = match context.document {
+        Some(document) => document,
+        None => return Err(PonTranslateErr::InvalidValue { value: format!("{} '{}' needs a document to look the resource up in", type_name, id) })
+    };
+    let resource = match document.resources.get(id) {
+        Some(resource) => resource,
+        None => return Err(PonTranslateErr::InvalidValue { value: format!("there is no resource '{}'", id) })
+    };
+    match resource.downcast_ref::<T>() {
+        Some(resource) => Ok(resource),
+        None => Err(PonTranslateErr::InvalidValue { value: format!("resource '{}' is not {}", id, kind) })
+    }
+}
+
+/// Translates the optional duration field, see translate_duration
+pub fn duration_field_or(pon: &Pon, field: &str, default: Duration, context: &mut TranslateContext) -> Result<Duration, PonTranslateErr> {
+    match pon.field(field) {
+        Ok(value) => translate_duration(value, context),
+        Err(_) => Ok(default)
+    }
+}
+
+/// Appends the properties in more that aren't in props already
+pub fn add_properties(props: &mut Vec<NamedPropRef>, more: Vec<NamedPropRef>) {
+    for prop in more {
+        if !props.contains(&prop) {
+            props.push(prop);
+        }
+    }
+}
+
+#[cfg(test)]
+pub fn this_props(names: &[&str]) -> Vec<NamedPropRef> {
+    names.iter().map(|name| NamedPropRef::new(EntityPath::This, name)).collect()
+}
+
+/// The values and events produced by Track::seek
+#[derive(PartialEq, Debug)]
+pub struct SeekResult {
+    pub values: Vec<(NamedPropRef, Animatable)>,
+    pub events: Vec<Event>
+}
+
+pub trait Track : Debug + ThreadSafety {
     fn value_at(&self, time: Duration) -> Vec<(NamedPropRef, Animatable)>;
+    /// Time from zero until the track has finished playing, including any offsets.
+    /// Tracks that never end return infinite_duration().
+    fn duration(&self) -> Duration;
+    /// The properties the track can write, each listed once, found without sampling it
+    fn affected_properties(&self) -> Vec<NamedPropRef>;
+    /// True when the track will only produce empty output from time on
+    fn is_finished(&self, time: Duration) -> bool {
+        time > self.duration()
+    }
+    /// Events with a time in from..to, so that stepping through time frame by frame
+    /// produces every event exactly once
+    fn events_between(&self, _from: Duration, _to: Duration) -> Vec<Event> {
+        vec![]
+    }
+    /// value_at for each of times, for sampling a whole track at once.
+    /// Sampling times in increasing order is the fastest.
+    fn value_at_many(&self, times: &[Duration]) -> Vec<Vec<(NamedPropRef, Animatable)>> {
+        times.iter().map(|&time| self.value_at(time)).collect()
+    }
+    /// Appends the values at time to out, so one buffer can be reused between frames
+    fn value_at_into(&self, time: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) {
+        out.extend(self.value_at(time).into_iter());
+    }
+    /// Replaces the values in out, which come from an earlier sample of this track, with the values
+    /// at time. Tracks that write the same properties every time update the values in place,
+    /// keeping the property refs already in out instead of cloning their names again.
+    fn value_at_reusing(&self, time: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) {
+        out.clear();
+        self.value_at_into(time, out);
+    }
+    /// True if the track writes the same values at every time from from to to, so values
+    /// sampled from it in that span can be reused
+    fn is_constant_between(&self, _from: Duration, _to: Duration) -> bool {
+        false
+    }
+    /// Moves playback from one time to another, as when scrubbing. The values are those at to.
+    /// Events between from and to fire when moving forwards, but not when scrubbing backwards,
+    /// so an event isn't fired again when playback goes back over it.
+    fn seek(&self, from: Duration, to: Duration) -> SeekResult {
+        let mut values = vec![];
+        let events = self.seek_reusing(from, to, &mut values);
+        SeekResult { values: values, events: events }
+    }
+    /// seek, with the values written to out like value_at_reusing does. Returns the events.
+    /// Tracks that change how seek works override this rather than seek.
+    fn seek_reusing(&self, from: Duration, to: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) -> Vec<Event> {
+        self.value_at_reusing(to, out);
+        if from < to { self.events_between(from, to) } else { vec![] }
+    }
+    /// The values at time by property. When a property is written more than once the last write wins,
+    /// track sets have already picked the write with the highest priority.
+    fn snapshot(&self, time: Duration) -> HashMap<NamedPropRef, Animatable> {
+        self.value_at(time).into_iter().collect()
+    }
+}
+
+/// Wraps boxed tracks in the wrapper tracks, so they can be composed by chaining
+pub trait TrackCombinators {
+    /// Starts the track later, see DelayTrack
+    fn delayed(self, delay: Duration) -> Box<Track>;
+    /// Plays the track the given number of times, see RepeatTrack
+    fn repeated(self, times: u32) -> Box<Track>;
+    /// Plays other once this track has finished, see SequenceTrack
+    fn then(self, other: Box<Track>) -> Box<Track>;
+    /// Writes to the mapped properties instead, see RetargetTrack
+    fn retargeted(self, mapping: HashMap<NamedPropRef, NamedPropRef>) -> Box<Track>;
+}
+
+impl TrackCombinators for Box<Track> {
+    fn delayed(self, delay: Duration) -> Box<Track> {
+        Box::new(DelayTrack { track: self, delay: delay })
+    }
+    fn repeated(self, times: u32) -> Box<Track> {
+        Box::new(RepeatTrack { track: self, times: Some(times) })
+    }
+    fn then(self, other: Box<Track>) -> Box<Track> {
+        Box::new(SequenceTrack { tracks: vec![self, other] })
+    }
+    fn retargeted(self, mapping: HashMap<NamedPropRef, NamedPropRef>) -> Box<Track> {
+        Box::new(RetargetTrack { track: self, mapping: mapping, strict: false })
+    }
+}
+
+/// Blends two snapshots, where a weight of 0 gives a and 1 gives b. A property in only one of them
+/// is scaled by that snapshot's weight, as if the other had it at zero. Properties with different
+/// component counts in a and b can't be blended, and are left out.
+pub fn blend_snapshots(a: &HashMap<NamedPropRef, Animatable>, b: &HashMap<NamedPropRef, Animatable>, weight: f32) -> HashMap<NamedPropRef, Animatable> {
+    let mut res = HashMap::new();
+    for (prop, value) in a {
+        match b.get(prop) {
+            Some(other) if other.value.len() == value.value.len() => {
+                res.insert(prop.clone(), Interpolateable::interpolate(value, other, &weight));
+            },
+            Some(_) => {},
+            None => {
+                res.insert(prop.clone(), value.weighted(1.0 - weight));
+            }
+        }
+    }
+    for (prop, value) in b {
+        if !a.contains_key(prop) {
+            res.insert(prop.clone(), value.weighted(weight));
+        }
+    }
+    res
 }
 
 #[derive(Debug)]
 struct TrackSetFromResource {
-    resource: Rc<TrackSet>
+    resource: Shared<TrackSet>
 }
 
 impl Track for TrackSetFromResource {
     fn value_at(&self, time: Duration) -> Vec<(NamedPropRef, Animatable)> {
         self.resource.value_at(time)
     }
+    fn affected_properties(&self) -> Vec<NamedPropRef> {
+        self.resource.affected_properties()
+    }
+    fn is_constant_between(&self, from: Duration, to: Duration) -> bool {
+        self.resource.is_constant_between(from, to)
+    }
+    fn duration(&self) -> Duration {
+        self.resource.duration()
+    }
+    fn is_finished(&self, time: Duration) -> bool {
+        self.resource.is_finished(time)
+    }
+    fn events_between(&self, from: Duration, to: Duration) -> Vec<Event> {
+        self.resource.events_between(from, to)
+    }
+    fn seek_reusing(&self, from: Duration, to: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) -> Vec<Event> {
+        self.resource.seek_reusing(from, to, out)
+    }
+    fn value_at_many(&self, times: &[Duration]) -> Vec<Vec<(NamedPropRef, Animatable)>> {
+        self.resource.value_at_many(times)
+    }
+    fn value_at_into(&self, time: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) {
+        self.resource.value_at_into(time, out)
+    }
+    fn value_at_reusing(&self, time: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) {
+        self.resource.value_at_reusing(time, out)
+    }
 }
 
 impl Translatable<Box<Track>> for Pon {
@@ -31,13 +271,121 @@ impl Translatable<Box<Track>> for Pon {
                 "fixed_value" => Ok(Box::new(try!(self.translate::<CurveTrack>(context)))),
                 "track_set" => Ok(Box::new(try!(self.translate::<TrackSet>(context)))),
                 "weighted_tracks" => Ok(Box::new(try!(self.translate::<WeightedTracks>(context)))),
+                "events" => Ok(Box::new(try!(self.translate::<EventTrack>(context)))),
+                "crossfade" => Ok(Box::new(try!(self.translate::<CrossfadeTrack>(context)))),
+                "sequence" => Ok(Box::new(try!(self.translate::<SequenceTrack>(context)))),
+                "delay" => Ok(Box::new(try!(self.translate::<DelayTrack>(context)))),
+                "repeat" => Ok(Box::new(try!(self.translate::<RepeatTrack>(context)))),
+                "retarget" => Ok(Box::new(try!(self.translate::<RetargetTrack>(context)))),
+                "time_remap" => Ok(Box::new(try!(self.translate::<TimeRemapTrack>(context)))),
+                "invert" => Ok(Box::new(try!(self.translate::<InvertTrack>(context)))),
+                "clamp_track" => Ok(Box::new(try!(self.translate::<ClampTrack>(context)))),
+                "sprite" => Ok(Box::new(try!(self.translate::<SpriteTrack>(context)))),
+                "split" => Ok(Box::new(try!(self.translate::<SplitTrack>(context)))),
+                "euler_rotation" => Ok(Box::new(try!(self.translate::<EulerRotationTrack>(context)))),
+                "bool_track" => Ok(Box::new(try!(self.translate::<BoolTrack>(context)))),
                 "track_set_from_resource" => {
                     let resource_id = try!(data.translate::<String>(context));
-                    let track_set = context.document.unwrap().resources.get(&resource_id).unwrap().downcast_ref::<Rc<TrackSet>>().unwrap().clone();
-                    return Ok(Box::new(TrackSetFromResource { resource: track_set }));
+                    let track_set = try!(find_resource::<Shared<TrackSet>>(context, type_name, &resource_id, "a track set"));
+                    Ok(Box::new(TrackSetFromResource { resource: track_set.clone() }))
                 },
                 s @ _ => Err(PonTranslateErr::UnrecognizedType(s.to_string()))
             }
         })
     }
 }
+
+#[test]
+fn test_translate_duration() {
+    let translate = |s: &str| translate_duration(&Pon::from_string(s).unwrap(), &mut TranslateContext::empty());
+    assert_eq!(translate("'500ms'").unwrap(), Duration::milliseconds(500));
+    assert_eq!(translate("'2s'").unwrap(), Duration::seconds(2));
+    assert_eq!(translate("'1.5s'").unwrap(), Duration::milliseconds(1500));
+    assert_eq!(translate("'2m'").unwrap(), Duration::minutes(2));
+    assert_eq!(translate("1.5").unwrap(), Duration::milliseconds(1500));
+    for invalid in &["'fast'", "'2h'", "'ms'", "'-1s'"] {
+        match translate(invalid) {
+            Err(PonTranslateErr::InvalidValue { .. }) => {},
+            r @ _ => panic!("Expected InvalidValue for {}, got {:?}", invalid, r)
+        }
+    }
+}
+
+#[cfg(test)]
+fn ramp(property: &str) -> Box<Track> {
+    Pon::from_string(&format!("key_framed {{ property: this.{}, keys: [[0.0, 0.0], [1.0, 1.0]], duration: 1.0 }}", property))
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap()
+}
+
+#[test]
+fn test_combinators() {
+    let x = |v: f32| vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(v))];
+    let track = ramp("x").delayed(Duration::milliseconds(500)).repeated(2);
+    assert_eq!(track.duration(), Duration::seconds(3));
+    assert_eq!(track.value_at(Duration::milliseconds(250)), vec![]);
+    assert_eq!(track.value_at(Duration::milliseconds(1000)), x(0.5));
+    assert_eq!(track.value_at(Duration::milliseconds(1750)), vec![]);
+    assert_eq!(track.value_at(Duration::milliseconds(2500)), x(0.5));
+    assert_eq!(track.value_at(Duration::milliseconds(3500)), vec![]);
+
+    let mut mapping = HashMap::new();
+    mapping.insert(NamedPropRef::new(EntityPath::This, "x"), NamedPropRef::new(EntityPath::This, "z"));
+    let track = ramp("x").then(ramp("y")).retargeted(mapping);
+    assert_eq!(track.duration(), Duration::seconds(2));
+    assert_eq!(track.value_at(Duration::milliseconds(500)), vec![(NamedPropRef::new(EntityPath::This, "z"), Animatable::new_float(0.5))]);
+    assert_eq!(track.value_at(Duration::milliseconds(1500)), vec![(NamedPropRef::new(EntityPath::This, "y"), Animatable::new_float(0.5))]);
+}
+
+#[test]
+fn test_add_properties() {
+    let mut props = this_props(&["x", "y"]);
+    add_properties(&mut props, this_props(&["y", "z", "z"]));
+    assert_eq!(props, this_props(&["x", "y", "z"]));
+}
+
+#[cfg(test)]
+fn resource_error(document: Option<&Document>) -> String {
+    let pon = Pon::from_string("track_set_from_resource 'walk'").unwrap();
+    match pon.translate::<Box<Track>>(&mut TranslateContext { document: document }) {
+        Err(PonTranslateErr::InvalidValue { value }) => value,
+        r @ _ => panic!("Expected InvalidValue, got {:?}", r)
+    }
+}
+
+#[test]
+fn test_track_set_from_resource_errors() {
+    assert!(resource_error(None).contains("needs a document"));
+    let mut document = Document::new();
+    assert!(resource_error(Some(&document)).contains("there is no resource 'walk'"));
+    document.resources.insert("walk".to_string(), Box::new(0.5f32) as Box<Any>);
+    assert!(resource_error(Some(&document)).contains("is not a track set"));
+}
+
+#[test]
+fn test_track_set_from_resource() {
+    let mut document = Document::new();
+    let track_set: TrackSet = Pon::from_string("track_set [ fixed_value { property: this.x, value: 0.5 } ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    document.resources.insert("walk".to_string(), Box::new(Shared::new(track_set)) as Box<Any>);
+    let track: Box<Track> = Pon::from_string("track_set_from_resource 'walk'").unwrap()
+        .translate(&mut TranslateContext { document: Some(&document) }).unwrap();
+    assert_eq!(track.value_at(Duration::zero()), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.5))]);
+}
+
+#[test]
+fn test_blend_snapshots() {
+    let prop = |name: &str| NamedPropRef::new(EntityPath::This, name);
+    let mut a = HashMap::new();
+    a.insert(prop("x"), Animatable::new_float(0.0));
+    a.insert(prop("y"), Animatable::new_float(4.0));
+    a.insert(prop("color"), Animatable::new(vec![1.0, 1.0, 1.0]));
+    let mut b = HashMap::new();
+    b.insert(prop("x"), Animatable::new_float(8.0));
+    b.insert(prop("z"), Animatable::new(vec![4.0, 8.0]));
+    b.insert(prop("color"), Animatable::new(vec![1.0, 1.0, 1.0, 1.0]));
+    let blended = blend_snapshots(&a, &b, 0.25);
+    assert_eq!(blended.len(), 3);
+    assert_eq!(blended[&prop("x")], Animatable::new_float(2.0));
+    assert_eq!(blended[&prop("y")], Animatable::new_float(3.0));
+    assert_eq!(blended[&prop("z")], Animatable::new(vec![1.0, 2.0]));
+}
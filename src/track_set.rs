@@ -1,37 +1,310 @@
 #[cfg(test)]
 use std::cmp::Ordering;
+#[cfg(test)]
+use curve_track::*;
+#[cfg(all(feature = "sync", test))]
+use std::sync::Arc;
+#[cfg(all(feature = "sync", test))]
+use std::thread;
 
+use std::mem;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use time::*;
 use track::*;
 use pyramid::pon::*;
 use animatable::*;
+use event_track::*;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+#[derive(Debug)]
+pub struct TrackSetEntry {
+    pub track: Box<Track>,
+    /// When several tracks write the same property, the one with the highest priority wins
+    pub priority: i32,
+    /// Muted tracks don't contribute to the set
+    pub muted: bool,
+    /// If any track in the set is soloed, only the soloed tracks contribute
+    pub solo: bool
+}
+
+impl TrackSetEntry {
+    pub fn new(track: Box<Track>) -> TrackSetEntry {
+        TrackSetEntry {
+            track: track,
+            priority: 0,
+            muted: false,
+            solo: false
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct TrackSet {
-    pub tracks: Vec<Box<Track>>
+    pub tracks: Vec<TrackSetEntry>,
+    /// Scales the time the tracks are sampled at, 2.0 plays the whole set twice as fast.
+    /// Applies on top of the tracks' own speed.
+    pub time_scale: f32
+}
+
+fn is_audible(entry: &TrackSetEntry, any_solo: bool) -> bool {
+    !entry.muted && (entry.solo || !any_solo)
+}
+
+impl TrackSet {
+    /// A set playing every track with the default priority, unmuted
+    pub fn new(tracks: Vec<Box<Track>>) -> TrackSet {
+        TrackSet::from_entries(tracks.into_iter().map(TrackSetEntry::new).collect())
+    }
+    pub fn from_entries(entries: Vec<TrackSetEntry>) -> TrackSet {
+        TrackSet {
+            tracks: entries,
+            time_scale: 1.0
+        }
+    }
+    /// The time the tracks are sampled at for time into the set
+    pub fn track_time(&self, time: Duration) -> Duration {
+        if self.time_scale == 1.0 || time == infinite_duration() {
+            return time;
+        }
+        Duration::milliseconds((time.num_milliseconds() as f64 * self.time_scale as f64) as i64)
+    }
+    /// The tracks that contribute to the set, taking mute and solo into account
+    pub fn audible_tracks(&self) -> Vec<&TrackSetEntry> {
+        let any_solo = self.tracks.iter().any(|entry| entry.solo);
+        self.tracks.iter().filter(|entry| is_audible(entry, any_solo)).collect()
+    }
+    /// The time the last track in the set ends, or None if any of them play forever
+    pub fn total_duration(&self) -> Option<Duration> {
+        let duration = self.duration();
+        if duration == infinite_duration() {
+            None
+        } else {
+            Some(duration)
+        }
+    }
+}
+
+impl TrackSet {
+    /// value_at, with the tracks evaluated in parallel. The output is the same as value_at's.
+    #[cfg(feature = "parallel")]
+    pub fn value_at_parallel(&self, time: Duration) -> Vec<(NamedPropRef, Animatable)> {
+        let entries = self.audible_tracks();
+        let time = self.track_time(time);
+        let values: Vec<Vec<(NamedPropRef, Animatable)>> = entries.par_iter().map(|entry| entry.track.value_at(time)).collect();
+        merge_by_priority(entries.into_iter().zip(values.into_iter()))
+    }
+}
+
+/// Values of a TrackSet's tracks sampled by value_at_cached, kept by the caller between frames
+#[derive(Debug)]
+pub struct TrackSetCache {
+    /// For each track in the set, the time it was sampled at and its values then
+    samples: Vec<Option<(Duration, Vec<(NamedPropRef, Animatable)>)>>,
+    reused: usize
+}
+
+impl TrackSetCache {
+    pub fn new() -> TrackSetCache {
+        TrackSetCache {
+            samples: vec![],
+            reused: 0
+        }
+    }
+    /// Number of times a track's values were reused instead of sampling the track
+    pub fn reused(&self) -> usize {
+        self.reused
+    }
+}
+
+impl TrackSet {
+    /// value_at, reusing the values of tracks that are constant since the time they were cached at.
+    /// Only use a cache with one set, and don't change the set's tracks while using it.
+    pub fn value_at_cached(&self, time: Duration, cache: &mut TrackSetCache) -> Vec<(NamedPropRef, Animatable)> {
+        let time = self.track_time(time);
+        // Slots are kept for every track, muted or not, so muting or soloing a track
+        // doesn't shift other tracks' cached values onto it
+        cache.samples.truncate(self.tracks.len());
+        while cache.samples.len() < self.tracks.len() {
+            cache.samples.push(None);
+        }
+        let any_solo = self.tracks.iter().any(|entry| entry.solo);
+        let mut entries = vec![];
+        let mut values = vec![];
+        for (entry, sample) in self.tracks.iter().zip(cache.samples.iter_mut()) {
+            if !is_audible(entry, any_solo) {
+                continue;
+            }
+            entries.push(entry);
+            let reuse = match *sample {
+                Some((cached_time, _)) => {
+                    let (from, to) = if cached_time < time { (cached_time, time) } else { (time, cached_time) };
+                    entry.track.is_constant_between(from, to)
+                },
+                None => false
+            };
+            if reuse {
+                cache.reused += 1;
+            } else {
+                *sample = Some((time, entry.track.value_at(time)));
+            }
+            values.push(sample.as_ref().unwrap().1.clone());
+        }
+        merge_by_priority(entries.into_iter().zip(values.into_iter()))
+    }
+}
+
+/// Combines the values of each track, keeping the value from the highest priority track for each property
+fn merge_by_priority<'a, I>(values: I) -> Vec<(NamedPropRef, Animatable)>
+    where I: Iterator<Item=(&'a TrackSetEntry, Vec<(NamedPropRef, Animatable)>)> {
+    let mut res: Vec<(NamedPropRef, Animatable)> = vec![];
+    let mut written: HashMap<NamedPropRef, (usize, i32)> = HashMap::new();
+    for (entry, updates) in values {
+        for update in updates.into_iter() {
+            match written.entry(update.0.clone()) {
+                Entry::Occupied(mut o) => {
+                    let (index, priority) = *o.get();
+                    // Equal priorities resolve to the last track written
+                    if entry.priority >= priority {
+                        res[index] = update;
+                        o.insert((index, entry.priority));
+                    }
+                },
+                Entry::Vacant(v) => {
+                    v.insert((res.len(), entry.priority));
+                    res.push(update);
+                }
+            }
+        }
+    }
+    res
 }
 
 impl Track for TrackSet {
     fn value_at(&self, time: Duration) -> Vec<(NamedPropRef, Animatable)> {
+        let time = self.track_time(time);
+        merge_by_priority(self.audible_tracks().into_iter().map(|entry| (entry, entry.track.value_at(time))))
+    }
+    fn affected_properties(&self) -> Vec<NamedPropRef> {
         let mut res = vec![];
-        for track in &self.tracks {
-            for update in track.value_at(time).into_iter() {
-                res.push(update);
-            }
+        for entry in self.audible_tracks() {
+            add_properties(&mut res, entry.track.affected_properties());
         }
         res
     }
+    fn is_constant_between(&self, from: Duration, to: Duration) -> bool {
+        let (from, to) = (self.track_time(from), self.track_time(to));
+        self.audible_tracks().iter().all(|entry| entry.track.is_constant_between(from, to))
+    }
+    fn duration(&self) -> Duration {
+        let duration = self.audible_tracks().iter().map(|entry| entry.track.duration()).max().unwrap_or(Duration::zero());
+        if self.time_scale == 1.0 || duration == infinite_duration() {
+            return duration;
+        }
+        Duration::milliseconds((duration.num_milliseconds() as f64 / self.time_scale as f64) as i64)
+    }
+    fn is_finished(&self, time: Duration) -> bool {
+        let time = self.track_time(time);
+        self.audible_tracks().iter().all(|entry| entry.track.is_finished(time))
+    }
+    fn events_between(&self, from: Duration, to: Duration) -> Vec<Event> {
+        let (from, to) = (self.track_time(from), self.track_time(to));
+        let mut res = vec![];
+        for entry in self.audible_tracks() {
+            res.extend(entry.track.events_between(from, to).into_iter());
+        }
+        res
+    }
+    fn value_at_reusing(&self, time: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) {
+        let time = self.track_time(time);
+        self.sample_reusing(out, |track, buffer| track.value_at_reusing(time, buffer));
+    }
+    // Seeks each track, so tracks that depend on the previous time, like once_then_hold_one_frame, still work in a set
+    fn seek_reusing(&self, from: Duration, to: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) -> Vec<Event> {
+        let (from, to) = (self.track_time(from), self.track_time(to));
+        let mut events = vec![];
+        self.sample_reusing(out, |track, buffer| events.extend(track.seek_reusing(from, to, buffer).into_iter()));
+        events
+    }
+}
+
+impl TrackSet {
+    /// Samples each audible track into out by priority like value_at, handing each track its values
+    /// from the earlier sample in out to reuse. They can only be handed back when every track wrote
+    /// one value, as in sets of single property tracks, otherwise the tracks start from scratch.
+    fn sample_reusing<F>(&self, out: &mut Vec<(NamedPropRef, Animatable)>, mut sample: F)
+        where F: FnMut(&Track, &mut Vec<(NamedPropRef, Animatable)>) {
+        let entries = self.audible_tracks();
+        let reuse = out.len() == entries.len();
+        let mut previous = mem::replace(out, Vec::with_capacity(entries.len())).into_iter();
+        let mut priorities: Vec<i32> = Vec::with_capacity(entries.len());
+        let mut buffer = vec![];
+        for entry in entries {
+            buffer.clear();
+            if reuse {
+                buffer.extend(previous.next().into_iter());
+            }
+            sample(&*entry.track, &mut buffer);
+            buffer.reverse();
+            while let Some(update) = buffer.pop() {
+                // Searched instead of hashed, a HashMap would need its own copy of every property ref
+                match out.iter().position(|&(ref prop, _)| *prop == update.0) {
+                    Some(i) => if entry.priority >= priorities[i] {
+                        out[i] = update;
+                        priorities[i] = entry.priority;
+                    },
+                    None => {
+                        out.push(update);
+                        priorities.push(entry.priority);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Translatable<TrackSetEntry> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<TrackSetEntry, PonTranslateErr> {
+        match self {
+            &Pon::Object(..) => {
+                let priority: f32 = try!(self.field_as_or("priority", 0.0, context));
+                if priority.fract() != 0.0 {
+                    return Err(PonTranslateErr::InvalidValue { value: priority.to_string() });
+                }
+                Ok(TrackSetEntry {
+                    track: try!(self.field_as::<Box<Track>>("track", context)),
+                    priority: priority as i32,
+                    muted: try!(self.field_as_or("muted", false, context)),
+                    solo: try!(self.field_as_or("solo", false, context))
+                })
+            },
+            _ => Ok(TrackSetEntry::new(try!(self.translate::<Box<Track>>(context))))
+        }
+    }
 }
 
 impl Translatable<TrackSet> for Pon {
     fn inner_translate(&self, context: &mut TranslateContext) -> Result<TrackSet, PonTranslateErr> {
         self.as_typed(|&TypedPon { ref type_name, ref data }| {
             match type_name.as_str() {
-                "track_set" => {
-                    let anims = try!(data.translate::<PonAutoVec<Box<Track>>>(context));
-                    Ok(TrackSet {
-                        tracks: anims.0
-                    })
+                // Either `track_set [tracks]`, or `track_set { tracks: [tracks], time_scale }`
+                "track_set" => match data {
+                    &Pon::Object(..) => {
+                        let time_scale = try!(data.field_as_or("time_scale", 1.0, context));
+                        if time_scale <= 0.0 {
+                            return Err(PonTranslateErr::InvalidValue { value: format!("time_scale must be positive, got {}", time_scale) });
+                        }
+                        let anims = try!(data.field_as::<PonAutoVec<TrackSetEntry>>("tracks", context));
+                        Ok(TrackSet {
+                            tracks: anims.0,
+                            time_scale: time_scale
+                        })
+                    },
+                    _ => {
+                        let anims = try!(data.translate::<PonAutoVec<TrackSetEntry>>(context));
+                        Ok(TrackSet::from_entries(anims.0))
+                    }
                 },
                 s @ _ => Err(PonTranslateErr::UnrecognizedType(s.to_string()))
             }
@@ -49,3 +322,286 @@ fn test_track_set_from_pon() {
         (NamedPropRef::new(EntityPath::This, "y"), 0.2)
     ].sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal)));
 }
+
+#[test]
+fn test_track_set_events() {
+    let anim_set: TrackSet = Pon::from_string(
+        "track_set [ fixed_value { property: this.x, value: 0.5 }, events { events: [{ time: 0.5, name: 'footstep' }] } ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(anim_set.events_between(Duration::milliseconds(400), Duration::milliseconds(600)).len(), 1);
+    assert_eq!(anim_set.events_between(Duration::milliseconds(600), Duration::milliseconds(800)).len(), 0);
+}
+
+#[test]
+fn test_track_set_priority() {
+    let anim_set: TrackSet = Pon::from_string(
+        "track_set [
+            { track: fixed_value { property: this.x, value: 0.5 }, priority: 2 },
+            fixed_value { property: this.x, value: 0.2 },
+            fixed_value { property: this.y, value: 0.7 }
+        ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(anim_set.value_at(Duration::zero()), vec![
+        (NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.5)),
+        (NamedPropRef::new(EntityPath::This, "y"), Animatable::new_float(0.7))
+    ]);
+}
+
+#[test]
+fn test_track_set_fractional_priority() {
+    let res: Result<TrackSet, PonTranslateErr> = Pon::from_string(
+        "track_set [ { track: fixed_value { property: this.x, value: 0.5 }, priority: 1.7 } ]")
+        .unwrap().translate(&mut TranslateContext::empty());
+    assert!(res.is_err());
+}
+
+#[test]
+fn test_track_set_new() {
+    let track: Box<Track> = Box::new(CurveTrack::new_fixed_value(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.5)));
+    let anim_set = TrackSet::new(vec![track]);
+    assert_eq!(anim_set.tracks[0].priority, 0);
+    assert_eq!(anim_set.value_at(Duration::zero()), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.5))]);
+}
+
+#[test]
+fn test_track_set_duration() {
+    let anim_set: TrackSet = Pon::from_string(
+        "track_set [
+            key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], duration: 1.0 },
+            key_framed { property: this.y, keys: [[0.0, 0.0], [3.0, 1.0]], duration: 3.0 }
+        ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(anim_set.duration(), Duration::seconds(3));
+    let anim_set: TrackSet = Pon::from_string(
+        "track_set [ fixed_value { property: this.x, value: 0.5 } ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(anim_set.duration(), infinite_duration());
+}
+
+#[test]
+fn test_track_set_is_finished() {
+    let anim_set: TrackSet = Pon::from_string(
+        "track_set [
+            key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], duration: 1.0 },
+            key_framed { property: this.y, keys: [[0.0, 0.0], [2.0, 1.0]], duration: 2.0 }
+        ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert!(!anim_set.is_finished(Duration::milliseconds(1500)));
+    assert!(anim_set.is_finished(Duration::milliseconds(2500)));
+    let anim_set: TrackSet = Pon::from_string(
+        "track_set [
+            key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], duration: 1.0 },
+            key_framed { property: this.y, keys: [[0.0, 0.0], [1.0, 1.0]], duration: 1.0, loop: 'hold' }
+        ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert!(!anim_set.is_finished(Duration::seconds(10)));
+}
+
+#[cfg(test)]
+fn staggered_ramp(property: &str, offset_ms: i64, loop_type: Loop) -> TrackSetEntry {
+    let mut track: CurveTrack = Pon::from_string(
+        "key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], duration: 1.0 }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    track.property = NamedPropRef::new(EntityPath::This, property);
+    track.offset = Duration::milliseconds(offset_ms);
+    track.loop_type = loop_type;
+    TrackSetEntry::new(Box::new(track))
+}
+
+#[test]
+fn test_track_set_total_duration() {
+    let anim_set = TrackSet::from_entries(vec![
+        staggered_ramp("x", 0, Loop::Once),
+        staggered_ramp("y", 2500, Loop::Once),
+        staggered_ramp("z", 1000, Loop::Count(2))
+    ]);
+    assert_eq!(anim_set.total_duration(), Some(Duration::milliseconds(3500)));
+    let anim_set = TrackSet::from_entries(vec![
+        staggered_ramp("x", 0, Loop::Once),
+        staggered_ramp("y", 500, Loop::Forever)
+    ]);
+    assert_eq!(anim_set.total_duration(), None);
+}
+
+#[cfg(test)]
+fn mute_solo_set(flags: &str) -> TrackSet {
+    Pon::from_string(&format!(
+        "track_set [
+            {{ track: fixed_value {{ property: this.x, value: 0.5 }}, {} }},
+            fixed_value {{ property: this.y, value: 0.2 }},
+            {{ track: fixed_value {{ property: this.z, value: 0.7 }}, solo: false }}
+        ]", flags))
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap()
+}
+
+#[test]
+fn test_track_set_muted_forever() {
+    let anim_set: TrackSet = Pon::from_string(
+        "track_set [
+            key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]] },
+            { track: key_framed { property: this.y, keys: [[0.0, 0.0], [1.0, 1.0]], loop: 'forever' }, muted: true }
+        ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(anim_set.duration(), Duration::seconds(1));
+    assert_eq!(anim_set.total_duration(), Some(Duration::seconds(1)));
+    assert!(anim_set.is_finished(Duration::seconds(2)));
+}
+
+#[test]
+fn test_track_set_mute() {
+    assert_eq!(mute_solo_set("muted: true").value_at(Duration::zero()), vec![
+        (NamedPropRef::new(EntityPath::This, "y"), Animatable::new_float(0.2)),
+        (NamedPropRef::new(EntityPath::This, "z"), Animatable::new_float(0.7))
+    ]);
+}
+
+#[test]
+fn test_track_set_solo() {
+    assert_eq!(mute_solo_set("solo: true").value_at(Duration::zero()), vec![
+        (NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.5))
+    ]);
+}
+
+#[test]
+fn test_track_set_muted_solo() {
+    // A muted track stays muted even when soloed, and the solo still silences the others
+    assert_eq!(mute_solo_set("muted: true, solo: true").value_at(Duration::zero()), vec![]);
+}
+
+#[test]
+fn test_track_set_snapshot() {
+    let anim_set: TrackSet = Pon::from_string(
+        "track_set [
+            fixed_value { property: this.x, value: 0.5 },
+            fixed_value { property: this.x, value: 0.2 },
+            fixed_value { property: this.y, value: 0.7 }
+        ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let snapshot = anim_set.snapshot(Duration::zero());
+    assert_eq!(snapshot.len(), 2);
+    assert_eq!(snapshot[&NamedPropRef::new(EntityPath::This, "x")], Animatable::new_float(0.2));
+    assert_eq!(snapshot[&NamedPropRef::new(EntityPath::This, "y")], Animatable::new_float(0.7));
+}
+
+#[test]
+fn test_track_set_affected_properties() {
+    assert_eq!(mute_solo_set("priority: 1").affected_properties(), this_props(&["x", "y", "z"]));
+    assert_eq!(mute_solo_set("muted: true").affected_properties(), this_props(&["y", "z"]));
+    let anim_set: TrackSet = Pon::from_string(
+        "track_set [ fixed_value { property: this.x, value: 0.5 }, fixed_value { property: this.x, value: 0.2 } ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(anim_set.affected_properties(), this_props(&["x"]));
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_track_set_between_threads() {
+    let anim_set: TrackSet = Pon::from_string(
+        "track_set [ key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], duration: 1.0 } ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let anim_set = Arc::new(anim_set);
+    let handles: Vec<_> = (0..4).map(|i| {
+        let anim_set = anim_set.clone();
+        thread::spawn(move || anim_set.value_at(Duration::milliseconds(i * 250)))
+    }).collect();
+    for (i, handle) in handles.into_iter().enumerate() {
+        assert_eq!(handle.join().unwrap(), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(i as f32 * 0.25))]);
+    }
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn test_track_set_parallel() {
+    let anim_set: TrackSet = Pon::from_string(
+        "track_set [
+            key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], duration: 1.0 },
+            { track: fixed_value { property: this.x, value: 0.5 }, priority: -1 },
+            key_framed { property: this.y, keys: [[0.0, 1.0], [1.0, 0.0]], duration: 1.0 },
+            fixed_value { property: this.y, value: 0.2 },
+            { track: fixed_value { property: this.z, value: 0.7 }, muted: true }
+        ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    for i in 0..20 {
+        let time = Duration::milliseconds(i * 100);
+        assert_eq!(anim_set.value_at_parallel(time), anim_set.value_at(time));
+    }
+}
+
+#[test]
+fn test_track_set_value_at_cached() {
+    let anim_set: TrackSet = Pon::from_string(
+        "track_set [
+            fixed_value { property: this.x, value: 0.5 },
+            key_framed { property: this.y, keys: [[0.0, 0.0], [1.0, 1.0]], duration: 1.0 },
+            { track: fixed_value { property: this.y, value: 0.2 }, priority: -1 }
+        ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let mut cache = TrackSetCache::new();
+    for i in 0..20 {
+        let time = Duration::milliseconds(i * 100);
+        assert_eq!(anim_set.value_at_cached(time, &mut cache), anim_set.value_at(time));
+    }
+    // Both fixed values are reused on every frame after the first
+    assert_eq!(cache.reused(), 38);
+    assert!(!anim_set.is_constant_between(Duration::zero(), Duration::seconds(1)));
+}
+
+#[test]
+fn test_track_set_time_scale() {
+    let anim_set: TrackSet = Pon::from_string(
+        "track_set { time_scale: 2.0, tracks: [
+            key_framed { property: this.x, keys: [[0.0, 0.0], [2.0, 1.0]] },
+            events { events: [{ time: 1.0, name: 'footstep' }] }
+        ] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(anim_set.value_at(Duration::milliseconds(500)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(0.5))]);
+    assert_eq!(anim_set.duration(), Duration::seconds(1));
+    assert_eq!(anim_set.events_between(Duration::milliseconds(400), Duration::milliseconds(600)).len(), 1);
+    let res: Result<TrackSet, PonTranslateErr> = Pon::from_string("track_set { time_scale: 0.0, tracks: [] }")
+        .unwrap().translate(&mut TranslateContext::empty());
+    assert!(res.is_err());
+}
+
+#[test]
+fn test_seek_holds_end_frame() {
+    let set: TrackSet = Pon::from_string(
+        "track_set [ key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], loop: 'once_then_hold_one_frame' } ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let x = |value| vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(value))];
+    // The frame past the end still gets the end value, the frame after that gets nothing
+    assert_eq!(set.seek(Duration::milliseconds(900), Duration::milliseconds(1100)).values, x(1.0));
+    assert_eq!(set.seek(Duration::milliseconds(1100), Duration::milliseconds(1200)).values, vec![]);
+}
+
+#[test]
+fn test_value_at_reusing() {
+    let set: TrackSet = Pon::from_string(
+        "track_set [ key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]] },
+            delay { track: key_framed { property: this.y, keys: [[0.0, 1.0], [1.0, 0.0]] }, by: 0.0 } ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let mut out = vec![];
+    set.value_at_reusing(Duration::milliseconds(100), &mut out);
+    let names: Vec<*const u8> = out.iter().map(|value| value.0.property_key.as_ptr()).collect();
+    for i in 2..10 {
+        let time = Duration::milliseconds(i * 100);
+        set.value_at_reusing(time, &mut out);
+        assert_eq!(out, set.value_at(time));
+        // The property refs from the first sample are kept, their names aren't allocated again
+        assert_eq!(out.iter().map(|value| value.0.property_key.as_ptr()).collect::<Vec<*const u8>>(), names);
+    }
+    let mut seeked = vec![];
+    set.seek_reusing(Duration::zero(), Duration::milliseconds(500), &mut seeked);
+    assert_eq!(seeked, set.seek(Duration::zero(), Duration::milliseconds(500)).values);
+}
+
+#[test]
+fn test_value_at_cached_after_solo() {
+    let mut set: TrackSet = Pon::from_string(
+        "track_set [ fixed_value { property: this.x, value: 0.5 }, fixed_value { property: this.y, value: 0.2 } ]")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    let mut cache = TrackSetCache::new();
+    assert_eq!(set.value_at_cached(Duration::zero(), &mut cache).len(), 2);
+    set.tracks[1].solo = true;
+    assert_eq!(set.value_at_cached(Duration::milliseconds(100), &mut cache),
+        vec![(NamedPropRef::new(EntityPath::This, "y"), Animatable::new_float(0.2))]);
+}
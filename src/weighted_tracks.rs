@@ -9,26 +9,63 @@ use time::*;
 use track::*;
 use pyramid::pon::*;
 use animatable::*;
+use curve::*;
+use event_track::*;
 
 #[derive(Debug)]
 pub struct WeightedTrack {
     pub weight: f32,
+    /// When set, the weight is sampled from this curve (in seconds) instead
+    pub weight_curve: Option<Box<Curve<f32>>>,
     pub track: Box<Track>
 }
 
+impl WeightedTrack {
+    pub fn weight_at(&self, time: Duration) -> f32 {
+        match self.weight_curve {
+            Some(ref curve) => curve.value(time.num_milliseconds() as f32 / 1000.0),
+            None => self.weight
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum BlendMode {
+    /// The sum of every track's value scaled by its weight
+    Weighted,
+    /// The first track to write a property is the base, at its full value, and the
+    /// following tracks are added on top of it scaled by their weights
+    Additive
+}
+
 #[derive(Debug)]
 pub struct WeightedTracks {
-    pub tracks: Vec<WeightedTrack>
+    pub tracks: Vec<WeightedTrack>,
+    pub blend_mode: BlendMode,
+    /// Divide the weights by their sum, so they always add up to 1
+    pub normalize: bool
 }
 
-impl Track for WeightedTracks {
-    fn value_at(&self, time: Duration) -> Vec<(NamedPropRef, Animatable)> {
+impl WeightedTracks {
+    /// Blends the values of each track, sampled at time
+    fn blend(&self, time: Duration, values: Vec<Vec<(NamedPropRef, Animatable)>>) -> Vec<(NamedPropRef, Animatable)> {
+        let scale = if self.normalize {
+            let sum = self.tracks.iter().fold(0.0, |sum, track| sum + track.weight_at(time));
+            if sum == 0.0 {
+                return vec![];
+            }
+            1.0 / sum
+        } else {
+            1.0
+        };
         let mut by_props: HashMap<NamedPropRef, Animatable> = HashMap::new();
-        for track in &self.tracks {
-            for update in track.track.value_at(time) {
-                let new_value = match by_props.get(&update.0) {
-                    Some(value) => value.add_weighted(track.weight, &update.1),
-                    None => update.1.weighted(track.weight)
+        for (track, values) in self.tracks.iter().zip(values.into_iter()) {
+            let weight = track.weight_at(time) * scale;
+            for update in values {
+                let new_value = match (by_props.get(&update.0), &self.blend_mode) {
+                    (Some(value), _) => value.add_weighted(weight, &update.1),
+                    (None, &BlendMode::Weighted) => update.1.weighted(weight),
+                    (None, &BlendMode::Additive) => update.1
                 };
                 by_props.insert(update.0, new_value);
             }
@@ -37,9 +74,82 @@ impl Track for WeightedTracks {
     }
 }
 
+impl Track for WeightedTracks {
+    fn value_at(&self, time: Duration) -> Vec<(NamedPropRef, Animatable)> {
+        self.blend(time, self.tracks.iter().map(|track| track.track.value_at(time)).collect())
+    }
+    fn seek_reusing(&self, from: Duration, to: Duration, out: &mut Vec<(NamedPropRef, Animatable)>) -> Vec<Event> {
+        let mut values = vec![];
+        let mut events = vec![];
+        for track in &self.tracks {
+            let seek = track.track.seek(from, to);
+            values.push(seek.values);
+            events.extend(seek.events.into_iter());
+        }
+        *out = self.blend(to, values);
+        events
+    }
+    fn affected_properties(&self) -> Vec<NamedPropRef> {
+        let mut res = vec![];
+        for track in &self.tracks {
+            add_properties(&mut res, track.track.affected_properties());
+        }
+        res
+    }
+    fn duration(&self) -> Duration {
+        self.tracks.iter().map(|track| track.track.duration()).max().unwrap_or(Duration::zero())
+    }
+    fn is_finished(&self, time: Duration) -> bool {
+        self.tracks.iter().all(|track| track.track.is_finished(time))
+    }
+    fn events_between(&self, from: Duration, to: Duration) -> Vec<Event> {
+        let mut res = vec![];
+        for track in &self.tracks {
+            res.extend(track.track.events_between(from, to).into_iter());
+        }
+        res
+    }
+}
+
+impl Translatable<BlendMode> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<BlendMode, PonTranslateErr> {
+        match try!(self.translate::<String>(context)).as_str() {
+            "weighted" => Ok(BlendMode::Weighted),
+            "additive" => Ok(BlendMode::Additive),
+            _ => Err(PonTranslateErr::InvalidValue { value: format!("{:?}", self) })
+        }
+    }
+}
+
+impl Translatable<WeightedTrack> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<WeightedTrack, PonTranslateErr> {
+        let weight_curve = match self.field("weight_curve") {
+            Ok(curve) => Some(try!(curve.translate::<Box<Curve<f32>>>(context))),
+            Err(_) => None
+        };
+        Ok(WeightedTrack {
+            weight: try!(self.field_as_or("weight", 1.0, context)),
+            weight_curve: weight_curve,
+            track: try!(self.field_as::<Box<Track>>("track", context))
+        })
+    }
+}
+
 impl Translatable<WeightedTracks> for Pon {
     fn inner_translate(&self, context: &mut TranslateContext) -> Result<WeightedTracks, PonTranslateErr> {
-        unimplemented!()
+        self.as_typed(|&TypedPon { ref type_name, ref data }| {
+            match type_name.as_str() {
+                "weighted_tracks" => {
+                    let tracks: PonAutoVec<WeightedTrack> = try!(data.field_as("tracks", context));
+                    Ok(WeightedTracks {
+                        tracks: tracks.0,
+                        blend_mode: try!(data.field_as_or("blend_mode", BlendMode::Weighted, context)),
+                        normalize: try!(data.field_as_or("normalize", false, context))
+                    })
+                },
+                s @ _ => Err(PonTranslateErr::UnrecognizedType(s.to_string()))
+            }
+        })
     }
 }
 
@@ -48,13 +158,87 @@ impl Translatable<WeightedTracks> for Pon {
 fn test_tracks() {
     let setup = WeightedTracks {
         tracks: vec![
-            WeightedTrack { weight: 0.1, track: Box::new(CurveTrack::new_fixed_value(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(10.0))) },
-            WeightedTrack { weight: 0.5, track: Box::new(CurveTrack::new_fixed_value(NamedPropRef::new(EntityPath::This, "y"), Animatable::new_float(10.0))) },
-            WeightedTrack { weight: 0.2, track: Box::new(CurveTrack::new_fixed_value(NamedPropRef::new(EntityPath::This, "y"), Animatable::new_float(100.0))) },
-        ]
+            WeightedTrack { weight: 0.1, weight_curve: None, track: Box::new(CurveTrack::new_fixed_value(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(10.0))) },
+            WeightedTrack { weight: 0.5, weight_curve: None, track: Box::new(CurveTrack::new_fixed_value(NamedPropRef::new(EntityPath::This, "y"), Animatable::new_float(10.0))) },
+            WeightedTrack { weight: 0.2, weight_curve: None, track: Box::new(CurveTrack::new_fixed_value(NamedPropRef::new(EntityPath::This, "y"), Animatable::new_float(100.0))) },
+        ],
+        blend_mode: BlendMode::Weighted,
+        normalize: false
     };
     assert_eq!(setup.value_at(Duration::zero()).sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal)), vec![
         (NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(1.0)),
         (NamedPropRef::new(EntityPath::This, "y"), Animatable::new_float(25.0))
     ].sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal)));
 }
+
+#[test]
+fn test_additive_tracks() {
+    let setup: WeightedTracks = Pon::from_string(
+        "weighted_tracks { tracks: [
+            { weight: 0.5, track: fixed_value { property: this.x, value: 10.0 } },
+            { weight: 1.0, track: fixed_value { property: this.x, value: 2.0 } }
+        ], blend_mode: 'additive' }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(setup.value_at(Duration::zero()), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(12.0))]);
+    let weighted = WeightedTracks { tracks: setup.tracks, blend_mode: BlendMode::Weighted, normalize: false };
+    assert_eq!(weighted.value_at(Duration::zero()), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(7.0))]);
+}
+
+#[cfg(test)]
+fn two_fixed_x(weights: [f32; 2], normalize: bool) -> WeightedTracks {
+    WeightedTracks {
+        tracks: vec![
+            WeightedTrack { weight: weights[0], weight_curve: None, track: Box::new(CurveTrack::new_fixed_value(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(10.0))) },
+            WeightedTrack { weight: weights[1], weight_curve: None, track: Box::new(CurveTrack::new_fixed_value(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(20.0))) },
+        ],
+        blend_mode: BlendMode::Weighted,
+        normalize: normalize
+    }
+}
+
+#[test]
+fn test_normalized_weights() {
+    let normalized = two_fixed_x([2.0, 2.0], true);
+    let half = two_fixed_x([0.5, 0.5], false);
+    assert_eq!(normalized.value_at(Duration::zero()), half.value_at(Duration::zero()));
+    assert_eq!(normalized.value_at(Duration::zero()), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(15.0))]);
+    assert_eq!(two_fixed_x([0.0, 0.0], true).value_at(Duration::zero()), vec![]);
+}
+
+#[test]
+fn test_weight_curves() {
+    let setup: WeightedTracks = Pon::from_string(
+        "weighted_tracks { tracks: [
+            { weight_curve: key_framed { keys: [[0.0, 1.0], [1.0, 0.0]] }, track: fixed_value { property: this.x, value: 10.0 } },
+            { weight_curve: key_framed { keys: [[0.0, 0.0], [1.0, 1.0]] }, track: fixed_value { property: this.x, value: 20.0 } }
+        ] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(setup.value_at(Duration::zero()), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(10.0))]);
+    assert_eq!(setup.value_at(Duration::milliseconds(500)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(15.0))]);
+    assert_eq!(setup.value_at(Duration::seconds(1)), vec![(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(20.0))]);
+}
+
+#[test]
+fn test_weighted_tracks_duration() {
+    let setup: WeightedTracks = Pon::from_string(
+        "weighted_tracks { tracks: [
+            { weight: 0.5, track: key_framed { property: this.x, keys: [[0.0, 0.0], [2.0, 1.0]], duration: 2.0 } },
+            { weight: 0.5, track: key_framed { property: this.x, keys: [[0.0, 0.0], [1.0, 1.0]], duration: 1.0 } }
+        ] }")
+        .unwrap().translate(&mut TranslateContext::empty()).unwrap();
+    assert_eq!(setup.duration(), Duration::seconds(2));
+}
+
+#[test]
+fn test_weighted_affected_properties() {
+    let setup = WeightedTracks {
+        tracks: vec![
+            WeightedTrack { weight: 0.5, weight_curve: None, track: Box::new(CurveTrack::new_fixed_value(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(1.0))) },
+            WeightedTrack { weight: 0.5, weight_curve: None, track: Box::new(CurveTrack::new_fixed_value(NamedPropRef::new(EntityPath::This, "y"), Animatable::new_float(1.0))) },
+            WeightedTrack { weight: 0.5, weight_curve: None, track: Box::new(CurveTrack::new_fixed_value(NamedPropRef::new(EntityPath::This, "x"), Animatable::new_float(2.0))) }
+        ],
+        blend_mode: BlendMode::Weighted,
+        normalize: false
+    };
+    assert_eq!(setup.affected_properties(), this_props(&["x", "y"]));
+}